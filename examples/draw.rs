@@ -10,7 +10,6 @@ use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sera::*;
 use std::time::Duration;
-use std::{mem, slice};
 use rand::{Rng, ThreadRng};
 
 const MAX_FPS: u32 = 60;
@@ -131,18 +130,10 @@ fn draw_buffer_rotate_scaled(buf: &mut Buffer, _: &mut ThreadRng) {
     }
 }
 
-#[inline]
-pub fn as_bytes<T: Copy>(array: &[T]) -> &mut [u8] {
-    unsafe {
-        slice::from_raw_parts_mut(
-            mem::transmute(array.as_ptr()),
-            mem::size_of::<T>() * array.len(),
-        )
-    }
-}
-
-#[test]
-fn draw_test() {
+/* Interactive: opens a window and cycles through cases with the arrow keys.
+ * Run with `cargo run --example draw --features sdl2`; `tests/golden.rs`
+ * covers the same primitives headlessly */
+fn main() {
     let ctx = sdl2::init().unwrap();
     let sys = ctx.video().unwrap();
     let win: Window = sys.window("draw-test", 512u32, 512u32)
@@ -173,9 +164,9 @@ fn draw_test() {
             let surface = win.surface(&event_pump);
             match surface {
                 Ok(mut surface) => {
+                    let pitch = surface.pitch() as usize;
                     surface.with_lock_mut(|pixels| {
-                        let data = as_bytes(&buffer.pixels[..]);
-                        pixels[..(data.len())].copy_from_slice(data);
+                        buffer.store_bytes(pixels, PixelFormat::BGRA, pitch);
                     });
                     surface.finish().unwrap();
                 }
@@ -0,0 +1,146 @@
+use super::*;
+
+/* Blends `c` at `x`, `y` with the mode's alpha scaled by `cov`, the fraction
+ * of the pixel covered by the shape */
+fn plot<S: PixelsMut>(b: &mut Buffer<S>, c: Pixel, x: i32, y: i32, cov: f32) {
+    if cov <= 0f32 || x < b.clip.x || y < b.clip.y || x >= b.clip.x + b.clip.w
+        || y >= b.clip.y + b.clip.h
+    {
+        return;
+    }
+    let mut m = b.mode;
+    m.alpha = (f32::from(m.alpha) * cov.min(1f32) + 0.5) as u8;
+    b.blend_at(&m, c, x, y);
+}
+
+fn fpart(x: f32) -> f32 {
+    x - x.floor()
+}
+
+/* Xiaolin Wu's line algorithm; pixel centers lie on integer coordinates */
+pub fn line<S: PixelsMut>(
+    b: &mut Buffer<S>,
+    c: Pixel,
+    mut x0: f32,
+    mut y0: f32,
+    mut x1: f32,
+    mut y1: f32,
+) {
+    if x0 == x1 && y0 == y1 {
+        /* A point covers its whole pixel, as it does for `draw_line` */
+        return plot(b, c, x0.round() as i32, y0.round() as i32, 1f32);
+    }
+    let steep = (y1 - y0).abs() > (x1 - x0).abs();
+    if steep {
+        mem::swap(&mut x0, &mut y0);
+        mem::swap(&mut x1, &mut y1);
+    }
+    if x0 > x1 {
+        mem::swap(&mut x0, &mut x1);
+        mem::swap(&mut y0, &mut y1);
+    }
+    let dx = x1 - x0;
+    let gradient = if dx == 0f32 { 1f32 } else { (y1 - y0) / dx };
+
+    macro_rules! plot {
+        ($x:expr, $y:expr, $cov:expr) => {
+            if steep {
+                plot(b, c, $y, $x, $cov);
+            } else {
+                plot(b, c, $x, $y, $cov);
+            }
+        };
+    }
+
+    /* Endpoints cover only part of their column */
+    let xend0 = x0.round();
+    let yend0 = y0 + gradient * (xend0 - x0);
+    let xgap0 = 1f32 - fpart(x0 + 0.5);
+    let xend1 = x1.round();
+    let yend1 = y1 + gradient * (xend1 - x1);
+    let xgap1 = fpart(x1 + 0.5);
+    let (xpx0, xpx1) = (xend0 as i32, xend1 as i32);
+    if xpx0 == xpx1 {
+        /* Both ends in one column, draw it once */
+        let (y, gap) = ((yend0 + yend1) / 2f32, x1 - x0);
+        plot!(xpx0, y.floor() as i32, (1f32 - fpart(y)) * gap);
+        plot!(xpx0, y.floor() as i32 + 1, fpart(y) * gap);
+        return;
+    }
+    plot!(xpx0, yend0.floor() as i32, (1f32 - fpart(yend0)) * xgap0);
+    plot!(xpx0, yend0.floor() as i32 + 1, fpart(yend0) * xgap0);
+    plot!(xpx1, yend1.floor() as i32, (1f32 - fpart(yend1)) * xgap1);
+    plot!(xpx1, yend1.floor() as i32 + 1, fpart(yend1) * xgap1);
+
+    /* Clipped columns need not be walked */
+    let (lo, hi) = if steep {
+        (b.clip.y - 1, b.clip.y + b.clip.h)
+    } else {
+        (b.clip.x - 1, b.clip.x + b.clip.w)
+    };
+    let start = (xpx0 + 1).max(lo);
+    let mut intery = yend0 + gradient * (start - xpx0) as f32;
+    for x in start..xpx1.min(hi + 1) {
+        plot!(x, intery.floor() as i32, 1f32 - fpart(intery));
+        plot!(x, intery.floor() as i32 + 1, fpart(intery));
+        intery += gradient;
+    }
+}
+
+/* Calls `f` with the range of rows and, per row, the horizontal extent of the
+ * pixels within `outer` of (`x`, `y`) that are not within `inner`, clipped to
+ * the buffer's clip rect */
+fn rows<S, F>(b: &mut Buffer<S>, x: f32, y: f32, inner: f32, outer: f32, mut f: F)
+where
+    S: PixelsMut,
+    F: FnMut(&mut Buffer<S>, i32, i32, i32),
+{
+    let clip = b.clip;
+    let y0 = ((y - outer).ceil() as i32).max(clip.y);
+    let y1 = ((y + outer).floor() as i32).min(clip.y + clip.h - 1);
+    for py in y0..(y1 + 1) {
+        let dy = py as f32 - y;
+        let wo = (outer * outer - dy * dy).max(0f32).sqrt();
+        let x0 = ((x - wo).ceil() as i32).max(clip.x);
+        let x1 = ((x + wo).floor() as i32).min(clip.x + clip.w - 1);
+        if inner > dy.abs() {
+            /* Split around the hole, keeping the spans apart */
+            let wi = (inner * inner - dy * dy).sqrt();
+            let left = ((x - wi).floor() as i32).min(x1);
+            let right = ((x + wi).ceil() as i32).max(left + 1).max(x0);
+            f(b, py, x0, left);
+            f(b, py, right, x1);
+        } else {
+            f(b, py, x0, x1);
+        }
+    }
+}
+
+/* Filled circle, pixels on the edge are weighted by their distance to it */
+pub fn circle<S: PixelsMut>(b: &mut Buffer<S>, c: &Paint, x: f32, y: f32, radius: f32) {
+    let r = radius.abs();
+    let solid = (r - 0.5).max(0f32);
+    /* Pixels within `solid` are fully covered */
+    rows(b, x, y, 0f32, r + 0.5, |b, py, x0, x1| {
+        let dy = py as f32 - y;
+        for px in x0..(x1 + 1) {
+            let dx = px as f32 - x;
+            let d2 = dx * dx + dy * dy;
+            let cov = if d2 <= solid * solid { 1f32 } else { r + 0.5 - d2.sqrt() };
+            plot(b, paint::sample(c, px, py), px, py, cov);
+        }
+    });
+}
+
+/* One pixel wide circle outline, pixels are weighted by their distance to
+ * the radius */
+pub fn ring<S: PixelsMut>(b: &mut Buffer<S>, c: Pixel, x: f32, y: f32, radius: f32) {
+    let r = radius.abs();
+    rows(b, x, y, r - 1f32, r + 1f32, |b, py, x0, x1| {
+        let dy = py as f32 - y;
+        for px in x0..(x1 + 1) {
+            let dx = px as f32 - x;
+            plot(b, c, px, py, 1f32 - ((dx * dx + dy * dy).sqrt() - r).abs());
+        }
+    });
+}
@@ -1,7 +1,13 @@
 use super::*;
 use super::util::*;
 
-pub fn basic(b: &mut Buffer, src: &Buffer, mut x: i32, mut y: i32, mut sub: Rect) {
+pub fn basic<D: PixelsMut, S: Pixels>(
+    b: &mut Buffer<D>,
+    src: &Buffer<S>,
+    mut x: i32,
+    mut y: i32,
+    mut sub: Rect,
+) {
     /* Clip to destination buffer */
     clip_rect_offset(&mut sub, &mut x, &mut y, b.clip);
     /* Clipped off screen? */
@@ -11,21 +17,23 @@ pub fn basic(b: &mut Buffer, src: &Buffer, mut x: i32, mut y: i32, mut sub: Rect
     /* Copy pixels */
     for i in 0..sub.h {
         for j in 0..sub.w {
-            b.pixels[(x + (y + i) * b.w + j) as usize] =
-                src.pixels[(sub.x + (sub.y + i) * src.w + j) as usize];
+            b.pixels[(x + (y + i) * b.pitch + j) as usize] = src.fmt
+                .convert(src.pixels[(sub.x + (sub.y + i) * src.pitch + j) as usize], b.fmt);
         }
     }
 }
 
-pub fn scaled(
-    b: &mut Buffer,
-    src: &Buffer,
+pub fn scaled<D: PixelsMut, S: Pixels>(
+    b: &mut Buffer<D>,
+    src: &Buffer<S>,
     mut x: i32,
     mut y: i32,
     mut sub: Rect,
     scalex: f32,
     scaley: f32,
 ) {
+    /* Clipping moves `sub`, filters may still read the pixels it left */
+    let bounds = sub;
     let mut width = (sub.w as f32 * scalex) as i32;
     let mut height = (sub.h as f32 * scaley) as i32;
     let inx = (FX_UNIT_12 as f32 / scalex) as i32;
@@ -56,15 +64,14 @@ pub fn scaled(
         return;
     }
     /* Draw */
+    let filter = b.mode.filter;
     let mut sy = sub.y << FX_BITS_12;
     for dy in y..(y + height) {
-        let mut sx = 0;
-        let mut dx = x + b.w * dy;
+        let mut sx = sub.x << FX_BITS_12;
+        let mut dx = x + b.pitch * dy;
         let edx = dx + width;
         while dx < edx {
-            b.pixels[dx as usize] = src.pixels[(((sub.x >> FX_BITS_12) + src.w * (sy >> FX_BITS_12))
-                                                   + (sx >> FX_BITS_12))
-                                                   as usize];
+            b.pixels[dx as usize] = b.fmt.store(filter::sample(src, &bounds, sx, sy, filter));
             sx += inx;
             dx += 1;
         }
@@ -1,7 +1,13 @@
 use super::*;
 use super::util::*;
 
-pub fn basic(b: &mut Buffer, src: &Buffer, mut x: i32, mut y: i32, mut sub: Rect) {
+pub fn basic<D: PixelsMut, S: Pixels>(
+    b: &mut Buffer<D>,
+    src: &Buffer<S>,
+    mut x: i32,
+    mut y: i32,
+    mut sub: Rect,
+) {
     /* Clip to destination buffer */
     clip_rect_offset(&mut sub, &mut x, &mut y, b.clip);
     /* Clipped off screen? */
@@ -9,21 +15,25 @@ pub fn basic(b: &mut Buffer, src: &Buffer, mut x: i32, mut y: i32, mut sub: Rect
         return;
     }
     /* Draw */
-    for iy in 0..(sub.h as usize) {
-        let (mut d_off, mut s_off) = (0, 0);
-        for _ in (0..sub.w).rev() {
-            blend_pixel(
-                &b.mode,
-                &mut b.pixels[(x + (y + iy as i32) * b.w + d_off) as usize],
-                src.pixels[(sub.x + (sub.y + iy as i32) * src.w + s_off) as usize],
-            );
-            d_off += 1;
-            s_off += 1;
+    let mode = b.mode;
+    for iy in 0..sub.h {
+        for ix in 0..sub.w {
+            let c = src.fmt.load(src.pixels[(sub.x + ix + (sub.y + iy) * src.pitch) as usize]);
+            b.blend_at(&mode, c, x + ix, y + iy);
         }
     }
 }
 
-pub fn scaled(b: &mut Buffer, src: &Buffer, x: i32, y: i32, mut sub: Rect, t: Transform) {
+pub fn scaled<D: PixelsMut, S: Pixels>(
+    b: &mut Buffer<D>,
+    src: &Buffer<S>,
+    x: i32,
+    y: i32,
+    mut sub: Rect,
+    t: Transform,
+) {
+    /* Clipping moves `sub`, filters may still read the pixels it left */
+    let bounds = sub;
     let abs_sx =
         if t.sx < 0.0 { -t.sx } else { t.sx };
     let abs_sy =
@@ -75,17 +85,15 @@ pub fn scaled(b: &mut Buffer, src: &Buffer, x: i32, y: i32, mut sub: Rect, t: Tr
         width -= _d;
     }
     /* Draw */
+    let mode = b.mode;
     let mut sy = osy;
     while dy < height {
         let mut dx = odx;
         let mut sx = osx;
         while dx < width {
-            blend_pixel(
-                &b.mode,
-                &mut b.pixels[((x + dx) + (y + dy) * b.w) as usize],
-                src.pixels[((sub.x + (sx >> FX_BITS_12)) + (sub.y + (sy >> FX_BITS_12)) * src.w)
-                               as usize],
-            );
+            let (fx, fy) = ((sub.x << FX_BITS_12) + sx, (sub.y << FX_BITS_12) + sy);
+            let c = filter::sample(src, &bounds, fx, fy, mode.filter);
+            b.blend_at(&mode, c, x + dx, y + dy);
             sx += ix;
             dx += 1;
         }
@@ -94,9 +102,9 @@ pub fn scaled(b: &mut Buffer, src: &Buffer, x: i32, y: i32, mut sub: Rect, t: Tr
     }
 }
 
-fn scan_line(
-    b: &mut Buffer,
-    src: &Buffer,
+fn scan_line<D: PixelsMut, S: Pixels>(
+    b: &mut Buffer<D>,
+    src: &Buffer<S>,
     sub: &Rect,
     dy: i32,
     t: Transform,
@@ -151,20 +159,25 @@ fn scan_line(
         }
     }
     /* Draw */
+    let mode = b.mode;
     dx = left;
     while dx < right {
-        blend_pixel(
-            &b.mode,
-            &mut b.pixels[(dx + dy * b.w) as usize],
-            src.pixels[((sx >> FX_BITS_12) + (sy >> FX_BITS_12) * src.w) as usize],
-        );
+        let c = filter::sample(src, sub, sx, sy, mode.filter);
+        b.blend_at(&mode, c, dx, dy);
         sx += sx_incr;
         sy += sy_incr;
         dx += 1;
     }
 }
 
-pub fn rotate_scaled(b: &mut Buffer, src: &Buffer, x: i32, y: i32, sub: Rect, t: Transform) {
+pub fn rotate_scaled<D: PixelsMut, S: Pixels>(
+    b: &mut Buffer<D>,
+    src: &Buffer<S>,
+    x: i32,
+    y: i32,
+    sub: Rect,
+    t: Transform,
+) {
     let mut points: [Point; 4] = [
         Point::new(0, 0),
         Point::new(0, 0),
@@ -285,3 +298,373 @@ pub fn rotate_scaled(b: &mut Buffer, src: &Buffer, x: i32, y: i32, sub: Rect, t:
         }
     }
 }
+
+/* Range of `t` for which `v0 + dv * t` lies in `0..max` */
+fn span_within(v0: f32, dv: f32, max: f32) -> (f32, f32) {
+    if dv == 0f32 {
+        if v0 >= 0f32 && v0 < max {
+            (f32::NEG_INFINITY, f32::INFINITY)
+        } else {
+            (0f32, -1f32)
+        }
+    } else {
+        let (a, b) = (-v0 / dv, (max - v0) / dv);
+        (a.min(b), a.max(b))
+    }
+}
+
+/* Draws `sub` of `src` placed by `m`, mapping each destination pixel center
+ * back into the source with `inv` */
+pub fn affine<D: PixelsMut, S: Pixels>(
+    b: &mut Buffer<D>,
+    src: &Buffer<S>,
+    sub: Rect,
+    m: Affine2,
+    inv: Affine2,
+) {
+    /* Bounds of the transformed corners */
+    let (w, h) = (sub.w as f32, sub.h as f32);
+    let corners = [m.apply(0f32, 0f32), m.apply(w, 0f32), m.apply(w, h), m.apply(0f32, h)];
+    let (mut x0, mut y0, mut x1, mut y1) = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+    for &(x, y) in &corners {
+        x0 = x0.min(x);
+        y0 = y0.min(y);
+        x1 = x1.max(x);
+        y1 = y1.max(y);
+    }
+    if !(x0.is_finite() && y0.is_finite() && x1.is_finite() && y1.is_finite()) {
+        return;
+    }
+    let clip = b.clip;
+    let top = (y0.floor() as i32).max(clip.y);
+    let bottom = (y1.ceil() as i32).min(clip.y + clip.h);
+    let left = (x0.floor() as i32).max(clip.x);
+    let right = (x1.ceil() as i32).min(clip.x + clip.w);
+    let mode = b.mode;
+    for y in top..bottom {
+        /* Source position of the row's first pixel center, stepping by
+         * (`inv.xx`, `inv.yx`) per pixel */
+        let (u0, v0) = inv.apply(left as f32 + 0.5, y as f32 + 0.5);
+        let (ua, ub) = span_within(u0, inv.xx, w);
+        let (va, vb) = span_within(v0, inv.yx, h);
+        /* Widened by a pixel, the exact test below decides the edges */
+        let n = (right - left) as f32;
+        let start = (ua.max(va).floor() - 1f32).max(0f32).min(n) as i32;
+        let end = (ub.min(vb).ceil() + 1f32).max(0f32).min(n) as i32;
+        for x in (left + start)..(left + end) {
+            let (u, v) = inv.apply(x as f32 + 0.5, y as f32 + 0.5);
+            if u < 0f32 || v < 0f32 || u >= w || v >= h {
+                continue;
+            }
+            let fx = ((sub.x as f32 + u) * FX_UNIT_12 as f32) as i32;
+            let fy = ((sub.y as f32 + v) * FX_UNIT_12 as f32) as i32;
+            let c = filter::sample(src, &sub, fx, fy, mode.filter);
+            b.blend_at(&mode, c, x, y);
+        }
+    }
+}
+
+/* Returns the inverse of the projective map from the unit square to the quad
+ * `p`, as a row major 3x3 matrix up to a scale that cancels out when
+ * dividing, or `None` if the quad is degenerate */
+fn inverse_homography(p: &[(f32, f32)]) -> Option<[f32; 9]> {
+    /* Unit square to quad, as (`a u + b v + c`, `d u + e v + f`) over
+     * `g u + h v + 1` */
+    let (sx, sy) = (p[0].0 - p[1].0 + p[2].0 - p[3].0, p[0].1 - p[1].1 + p[2].1 - p[3].1);
+    let (dx1, dx2) = (p[1].0 - p[2].0, p[3].0 - p[2].0);
+    let (dy1, dy2) = (p[1].1 - p[2].1, p[3].1 - p[2].1);
+    let den = dx1 * dy2 - dx2 * dy1;
+    if den == 0f32 {
+        return None;
+    }
+    let g = (sx * dy2 - dx2 * sy) / den;
+    let h = (dx1 * sy - sx * dy1) / den;
+    let (a, b, c) = (p[1].0 - p[0].0 + g * p[1].0, p[3].0 - p[0].0 + h * p[3].0, p[0].0);
+    let (d, e, f) = (p[1].1 - p[0].1 + g * p[1].1, p[3].1 - p[0].1 + h * p[3].1, p[0].1);
+    /* The adjugate */
+    let inv = [
+        e - f * h,
+        c * h - b,
+        b * f - c * e,
+        f * g - d,
+        a - c * g,
+        c * d - a * f,
+        d * h - e * g,
+        b * g - a * h,
+        a * e - b * d,
+    ];
+    let det = a * inv[0] + b * inv[3] + c * inv[6];
+    if det == 0f32 || !det.is_finite() {
+        return None;
+    }
+    Some(inv)
+}
+
+/* Draws `sub` of `src` with its top left, top right, bottom right and bottom
+ * left corners at `quad`, mapping each destination pixel center back into
+ * the source through the inverse of the projective map between the two */
+pub fn quad<D: PixelsMut, S: Pixels>(
+    b: &mut Buffer<D>,
+    src: &Buffer<S>,
+    sub: Rect,
+    quad: [Point; 4],
+) {
+    let p: Vec<(f32, f32)> = quad.iter().map(|p| (p.x as f32, p.y as f32)).collect();
+    let inv = match inverse_homography(&p) {
+        Some(inv) => inv,
+        None => return,
+    };
+    /* Bounds of the quad */
+    let (mut x0, mut y0, mut x1, mut y1) = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+    for &(x, y) in &p {
+        x0 = x0.min(x);
+        y0 = y0.min(y);
+        x1 = x1.max(x);
+        y1 = y1.max(y);
+    }
+    let clip = b.clip;
+    let top = (y0.floor() as i32).max(clip.y);
+    let bottom = (y1.ceil() as i32).min(clip.y + clip.h);
+    let left = (x0.floor() as i32).max(clip.x);
+    let right = (x1.ceil() as i32).min(clip.x + clip.w);
+    let mode = b.mode;
+    for y in top..bottom {
+        let fy = y as f32 + 0.5;
+        for x in left..right {
+            let fx = x as f32 + 0.5;
+            let w = inv[6] * fx + inv[7] * fy + inv[8];
+            let u = (inv[0] * fx + inv[1] * fy + inv[2]) / w;
+            let v = (inv[3] * fx + inv[4] * fy + inv[5]) / w;
+            /* Also rejects the NaNs of points on the horizon */
+            if !(u >= 0f32 && v >= 0f32 && u < 1f32 && v < 1f32) {
+                continue;
+            }
+            let sx = ((sub.x as f32 + u * sub.w as f32) * FX_UNIT_12 as f32) as i32;
+            let sy = ((sub.y as f32 + v * sub.h as f32) * FX_UNIT_12 as f32) as i32;
+            let c = filter::sample(src, &sub, sx, sy, mode.filter);
+            b.blend_at(&mode, c, x, y);
+        }
+    }
+}
+
+/* Edge function of `a` to `b`, positive for points on one side of the edge
+ * and negative on the other */
+fn edge(a: &Vertex, b: &Vertex, x: f32, y: f32) -> f32 {
+    (b.x - a.x) * (y - a.y) - (b.y - a.y) * (x - a.x)
+}
+
+/* Whether pixel centers lying exactly on the edge from `a` to `b` belong to
+ * the triangle. Neighbours run a shared edge in opposite directions, so
+ * exactly one of them draws those pixels */
+fn owns_edge(a: &Vertex, b: &Vertex) -> bool {
+    a.y > b.y || (a.y == b.y && b.x > a.x)
+}
+
+/* Draws a triangle textured from `src` and tinted by its vertex colors,
+ * covering the pixels whose centers fall within it, one span per row.
+ * Perspective mapping skips triangles with a vertex at or behind the eye,
+ * `w <= 0`, as they'd need clipping first */
+pub fn triangle<D: PixelsMut, S: Pixels>(
+    b: &mut Buffer<D>,
+    src: &Buffer<S>,
+    mut t: [Vertex; 3],
+    mapping: TextureMapping,
+) {
+    let mut area = edge(&t[0], &t[1], t[2].x, t[2].y);
+    if area < 0f32 {
+        t.swap(1, 2);
+        area = -area;
+    }
+    if !(area > 0f32 && area.is_finite()) {
+        return;
+    }
+    if mapping == TextureMapping::PERSPECTIVE && !t.iter().all(|v| v.w > 0f32 && v.w.is_finite())
+    {
+        return;
+    }
+    /* Interpolating attributes over `w` makes them perspective correct */
+    let q: Vec<f32> = t.iter()
+        .map(|v| match mapping {
+            TextureMapping::AFFINE => 1f32,
+            TextureMapping::PERSPECTIVE => 1f32 / v.w,
+        })
+        .collect();
+    let attrs: Vec<[f32; 6]> = t.iter()
+        .zip(&q)
+        .map(|(v, q)| {
+            let c = unsafe { v.color.rgba };
+            [
+                v.u * q,
+                v.v * q,
+                f32::from(c.r) * q,
+                f32::from(c.g) * q,
+                f32::from(c.b) * q,
+                f32::from(c.a) * q,
+            ]
+        })
+        .collect();
+    /* Edges opposite each vertex */
+    let edges = [(1, 2), (2, 0), (0, 1)];
+    let owns: Vec<bool> = edges.iter().map(|&(i, j)| owns_edge(&t[i], &t[j])).collect();
+    let clip = b.clip;
+    let (y0, y1) = (t[0].y.min(t[1].y).min(t[2].y), t[0].y.max(t[1].y).max(t[2].y));
+    let top = (y0.floor() as i32).max(clip.y);
+    let bottom = (y1.ceil() as i32).min(clip.y + clip.h);
+    let whole = Rect::new(0, 0, src.w, src.h);
+    let mode = b.mode;
+    'rows: for y in top..bottom {
+        let fy = y as f32 + 0.5;
+        /* Narrow the row down to the pixel centers inside every edge */
+        let (mut left, mut right) = (clip.x, clip.x + clip.w);
+        for (k, &(i, j)) in edges.iter().enumerate() {
+            let (a, c) = (&t[i], &t[j]);
+            if a.y == c.y {
+                let e = edge(a, c, 0f32, fy);
+                if e < 0f32 || (e == 0f32 && !owns[k]) {
+                    continue 'rows;
+                }
+                continue;
+            }
+            /* Where the edge crosses the row, shifted to pixel centers */
+            let cross = a.x + (c.x - a.x) * (fy - a.y) / (c.y - a.y) - 0.5;
+            if c.y < a.y {
+                /* Inside lies to the right */
+                let mut x = cross.ceil();
+                if x == cross && !owns[k] {
+                    x += 1f32;
+                }
+                left = left.max(x.max(clip.x as f32) as i32);
+            } else {
+                let mut x = cross.floor() + 1f32;
+                if x - 1f32 == cross && !owns[k] {
+                    x -= 1f32;
+                }
+                right = right.min(x.min((clip.x + clip.w) as f32) as i32);
+            }
+        }
+        for x in left..right {
+            let fx = x as f32 + 0.5;
+            let mut weights = [0f32; 3];
+            for (w, &(i, j)) in weights.iter_mut().zip(&edges) {
+                *w = edge(&t[i], &t[j], fx, fy) / area;
+            }
+            let mut a = [0f32; 6];
+            for (w, attr) in weights.iter().zip(&attrs) {
+                for (a, v) in a.iter_mut().zip(attr) {
+                    *a += w * v;
+                }
+            }
+            let qz = weights[0] * q[0] + weights[1] * q[1] + weights[2] * q[2];
+            let (su, sv) = (a[0] / qz, a[1] / qz);
+            let s = filter::sample(
+                src,
+                &whole,
+                (su * FX_UNIT_12 as f32) as i32,
+                (sv * FX_UNIT_12 as f32) as i32,
+                mode.filter,
+            );
+            let s = unsafe { s.rgba };
+            let tint = |c: u8, t: f32| (f32::from(c) * (t / qz) / 255f32 + 0.5) as u8;
+            let c = Pixel::pixel(
+                tint(s.r, a[2]),
+                tint(s.g, a[3]),
+                tint(s.b, a[4]),
+                tint(s.a, a[5]),
+            );
+            b.blend_at(&mode, c, x, y);
+        }
+    }
+}
+
+/* Covers `dest` with copies of `sub`, showing the point `offset` into the
+ * repeating pattern at its top left corner. Walks the visible pixels once,
+ * wrapping the source position instead of drawing tile by tile */
+pub fn tiled<D: PixelsMut, S: Pixels>(
+    b: &mut Buffer<D>,
+    src: &Buffer<S>,
+    sub: Rect,
+    dest: Rect,
+    offset: Point,
+) {
+    if sub.w <= 0 || sub.h <= 0 {
+        return;
+    }
+    let mut vis = dest;
+    clip_rect(&mut vis, &b.clip);
+    if vis.w <= 0 || vis.h <= 0 {
+        return;
+    }
+    let mode = b.mode;
+    let sx0 = (vis.x - dest.x + offset.x).rem_euclid(sub.w);
+    let mut sy = (vis.y - dest.y + offset.y).rem_euclid(sub.h);
+    for y in vis.y..(vis.y + vis.h) {
+        let row = (sub.y + sy) * src.pitch + sub.x;
+        let mut sx = sx0;
+        for x in vis.x..(vis.x + vis.w) {
+            let c = src.fmt.load(src.pixels[(row + sx) as usize]);
+            b.blend_at(&mode, c, x, y);
+            sx += 1;
+            if sx == sub.w {
+                sx = 0;
+            }
+        }
+        sy += 1;
+        if sy == sub.h {
+            sy = 0;
+        }
+    }
+}
+
+/* Draws `sub` stretched over `dest` */
+fn stretched<D: PixelsMut, S: Pixels>(b: &mut Buffer<D>, src: &Buffer<S>, sub: Rect, dest: Rect) {
+    if sub.w <= 0 || sub.h <= 0 || dest.w <= 0 || dest.h <= 0 {
+        return;
+    }
+    if sub.w == dest.w && sub.h == dest.h {
+        basic(b, src, dest.x, dest.y, sub);
+    } else {
+        let (sx, sy) = (dest.w as f32 / sub.w as f32, dest.h as f32 / sub.h as f32);
+        scaled(b, src, dest.x, dest.y, sub, Transform::new(0f32, 0f32, 0f32, sx, sy));
+    }
+}
+
+/* Splits `len` into the sizes of the two borders and what's left between
+ * them, shrinking the borders in proportion if they don't fit */
+fn split(len: i32, start: i32, end: i32) -> [i32; 3] {
+    if start + end <= len {
+        [start, len - start - end, end]
+    } else {
+        let start = start * len / (start + end);
+        [start, 0, len - start]
+    }
+}
+
+pub fn nine_slice<D: PixelsMut, S: Pixels>(
+    b: &mut Buffer<D>,
+    src: &Buffer<S>,
+    sub: Rect,
+    slice: NineSlice,
+    dest: Rect,
+) {
+    let sw = [slice.left, sub.w - slice.left - slice.right, slice.right];
+    let sh = [slice.top, sub.h - slice.top - slice.bottom, slice.bottom];
+    let dw = split(dest.w, slice.left, slice.right);
+    let dh = split(dest.h, slice.top, slice.bottom);
+    let (mut sy, mut dy) = (sub.y, dest.y);
+    for j in 0..3 {
+        let (mut sx, mut dx) = (sub.x, dest.x);
+        for i in 0..3 {
+            let s = Rect::new(sx, sy, sw[i], sh[j]);
+            let d = Rect::new(dx, dy, dw[i], dh[j]);
+            if slice.tile && (i == 1 || j == 1) {
+                tiled(b, src, s, d, Point::new(0, 0));
+            } else {
+                stretched(b, src, s, d);
+            }
+            sx += sw[i];
+            dx += dw[i];
+        }
+        sy += sh[j];
+        dy += dh[j];
+    }
+}
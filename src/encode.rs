@@ -0,0 +1,335 @@
+use super::*;
+use std::io::{self, Write};
+
+/* Length and distance code tables from RFC 1951 section 3.2.5 */
+const LEN_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+    131, 163, 195, 227, 258,
+];
+const LEN_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+const WINDOW_SIZE: usize = 1 << 15;
+const HASH_BITS: u32 = 15;
+const MAX_CHAIN: usize = 64;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+
+lazy_static! {
+    static ref CRC_TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        for (n, t) in table.iter_mut().enumerate() {
+            let mut c = n as u32;
+            for _ in 0..8 {
+                c = if c & 1 != 0 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 };
+            }
+            *t = c;
+        }
+        table
+    };
+}
+
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    !data.iter().fold(!crc, |c, &b| {
+        CRC_TABLE[((c ^ u32::from(b)) & 0xff) as usize] ^ (c >> 8)
+    })
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &x in chunk {
+            a += u32::from(x);
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u32,
+    n: u32,
+}
+
+impl BitWriter {
+    fn put(&mut self, bits: u32, n: u32) {
+        self.acc |= bits << self.n;
+        self.n += n;
+        while self.n >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.n -= 8;
+        }
+    }
+
+    /* Huffman codes are packed starting from their most significant bit */
+    fn put_code(&mut self, code: u32, n: u32) {
+        self.put(code.reverse_bits() >> (32 - n), n);
+    }
+
+    fn put_literal(&mut self, v: u32) {
+        match v {
+            0..=143 => self.put_code(0x30 + v, 8),
+            144..=255 => self.put_code(0x190 + v - 144, 9),
+            256..=279 => self.put_code(v - 256, 7),
+            _ => self.put_code(0xc0 + v - 280, 8),
+        }
+    }
+
+    fn put_match(&mut self, len: usize, dist: usize) {
+        let li = LEN_BASE.iter().rposition(|&b| b as usize <= len).unwrap();
+        self.put_literal(257 + li as u32);
+        self.put((len - LEN_BASE[li] as usize) as u32, u32::from(LEN_EXTRA[li]));
+        let di = DIST_BASE.iter().rposition(|&b| b as usize <= dist).unwrap();
+        self.put_code(di as u32, 5);
+        self.put((dist - DIST_BASE[di] as usize) as u32, u32::from(DIST_EXTRA[di]));
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.n > 0 {
+            self.out.push(self.acc as u8);
+        }
+        self.out
+    }
+}
+
+fn hash3(data: &[u8], i: usize) -> usize {
+    let v = u32::from(data[i]) | u32::from(data[i + 1]) << 8 | u32::from(data[i + 2]) << 16;
+    (v.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+}
+
+/* Compresses `data` into a zlib stream made of a single fixed-Huffman block,
+ * finding matches with a hash-chained LZ77 search */
+fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut bw = BitWriter {
+        out: vec![0x78, 0x01],
+        acc: 0,
+        n: 0,
+    };
+    bw.put(1, 1);
+    bw.put(1, 2);
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW_SIZE];
+    let mut i = 0;
+    while i < data.len() {
+        let (mut best_len, mut best_dist) = (0, 0);
+        if i + MIN_MATCH <= data.len() {
+            let max = MAX_MATCH.min(data.len() - i);
+            let mut cand = head[hash3(data, i)];
+            let mut chain = 0;
+            while cand != usize::MAX && i - cand < WINDOW_SIZE && chain < MAX_CHAIN {
+                let len = data[cand..]
+                    .iter()
+                    .zip(&data[i..(i + max)])
+                    .take_while(|&(a, b)| a == b)
+                    .count();
+                if len > best_len {
+                    best_len = len;
+                    best_dist = i - cand;
+                    if len == max {
+                        break;
+                    }
+                }
+                cand = prev[cand % WINDOW_SIZE];
+                chain += 1;
+            }
+        }
+        let n = if best_len >= MIN_MATCH {
+            bw.put_match(best_len, best_dist);
+            best_len
+        } else {
+            bw.put_literal(u32::from(data[i]));
+            1
+        };
+        /* Remember every position we pass over as a future match candidate */
+        for j in i..(i + n) {
+            if j + MIN_MATCH <= data.len() {
+                let h = hash3(data, j);
+                prev[j % WINDOW_SIZE] = head[h];
+                head[h] = j;
+            }
+        }
+        i += n;
+    }
+    bw.put_literal(256);
+    let mut out = bw.finish();
+    out.extend_from_slice(&be32(adler32(data)));
+    out
+}
+
+fn be32(v: u32) -> [u8; 4] {
+    [(v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8, v as u8]
+}
+
+fn le32(v: u32) -> [u8; 4] {
+    [v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8]
+}
+
+fn le16(v: u16) -> [u8; 2] {
+    [v as u8, (v >> 8) as u8]
+}
+
+/* The error for buffers whose size can't be stored in `format`'s header */
+fn too_large(b: &Buffer, format: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{}x{} buffer is too large for {}", b.w, b.h, format),
+    )
+}
+
+fn rgba(p: Pixel) -> [u8; 4] {
+    unsafe { [p.rgba.r, p.rgba.g, p.rgba.b, p.rgba.a] }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = i16::from(a) + i16::from(b) - i16::from(c);
+    let (pa, pb, pc) = (
+        (p - i16::from(a)).abs(),
+        (p - i16::from(b)).abs(),
+        (p - i16::from(c)).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+fn png_chunk<W: Write>(w: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    w.write_all(&be32(data.len() as u32))?;
+    w.write_all(kind)?;
+    w.write_all(data)?;
+    w.write_all(&be32(crc32(crc32(0, kind), data)))
+}
+
+pub fn png<W: Write>(b: &Buffer, w: &mut W) -> io::Result<()> {
+    let stride = b.w as usize * 4;
+    let mut raw = Vec::with_capacity((stride + 1) * b.h as usize);
+    let mut last = vec![0u8; stride];
+    let mut line = Vec::with_capacity(stride);
+    let mut filtered = vec![vec![0u8; stride]; 4];
+    for row in b.pixels.chunks(b.w as usize) {
+        line.clear();
+        for p in row {
+            line.extend_from_slice(&rgba(b.fmt.load(*p)));
+        }
+        /* Try each filter and keep the one with the smallest sum of absolute
+         * differences, as suggested by the PNG specification */
+        for i in 0..stride {
+            let a = if i >= 4 { line[i - 4] } else { 0 };
+            let c = if i >= 4 { last[i - 4] } else { 0 };
+            filtered[0][i] = line[i].wrapping_sub(a);
+            filtered[1][i] = line[i].wrapping_sub(last[i]);
+            filtered[2][i] = line[i].wrapping_sub(((u16::from(a) + u16::from(last[i])) / 2) as u8);
+            filtered[3][i] = line[i].wrapping_sub(paeth(a, last[i], c));
+        }
+        let cost = |r: &[u8]| r.iter().map(|&v| i32::from(v as i8).unsigned_abs()).sum::<u32>();
+        let mut best = (0, cost(&line));
+        for (f, r) in filtered.iter().enumerate() {
+            let c = cost(r);
+            if c < best.1 {
+                best = (f + 1, c);
+            }
+        }
+        raw.push(best.0 as u8);
+        raw.extend_from_slice(if best.0 == 0 { &line } else { &filtered[best.0 - 1] });
+        last.copy_from_slice(&line);
+    }
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&be32(b.w as u32));
+    ihdr.extend_from_slice(&be32(b.h as u32));
+    /* 8 bits per channel, RGBA, deflate, adaptive filtering, no interlace */
+    ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+    w.write_all(b"\x89PNG\r\n\x1a\n")?;
+    png_chunk(w, b"IHDR", &ihdr)?;
+    png_chunk(w, b"IDAT", &zlib(&raw))?;
+    png_chunk(w, b"IEND", &[])
+}
+
+pub fn bmp<W: Write>(b: &Buffer, w: &mut W) -> io::Result<()> {
+    let size = (b.w as u32)
+        .checked_mul(b.h as u32)
+        .and_then(|n| n.checked_mul(4))
+        .filter(|n| n.checked_add(14 + 40).is_some())
+        .ok_or_else(|| too_large(b, "BMP"))?;
+    /* BITMAPFILEHEADER */
+    w.write_all(b"BM")?;
+    w.write_all(&le32(14 + 40 + size))?;
+    w.write_all(&le32(0))?;
+    w.write_all(&le32(14 + 40))?;
+    /* BITMAPINFOHEADER, 32bpp uncompressed with rows stored bottom-up */
+    w.write_all(&le32(40))?;
+    w.write_all(&le32(b.w as u32))?;
+    w.write_all(&le32(b.h as u32))?;
+    w.write_all(&le16(1))?;
+    w.write_all(&le16(32))?;
+    w.write_all(&le32(0))?;
+    w.write_all(&le32(size))?;
+    w.write_all(&le32(2835))?;
+    w.write_all(&le32(2835))?;
+    w.write_all(&le32(0))?;
+    w.write_all(&le32(0))?;
+    let mut line = Vec::with_capacity(b.w as usize * 4);
+    for row in b.pixels.chunks(b.w as usize).rev() {
+        line.clear();
+        for p in row {
+            let [r, g, b, a] = rgba(b.fmt.load(*p));
+            line.extend_from_slice(&[b, g, r, a]);
+        }
+        w.write_all(&line)?;
+    }
+    Ok(())
+}
+
+pub fn tga<W: Write>(b: &Buffer, w: &mut W) -> io::Result<()> {
+    if b.w > 0xffff || b.h > 0xffff {
+        return Err(too_large(b, "TGA"));
+    }
+    /* Run-length encoded truecolor, 32bpp with 8 alpha bits, top-left origin */
+    w.write_all(&[0, 0, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0])?;
+    w.write_all(&le16(b.w as u16))?;
+    w.write_all(&le16(b.h as u16))?;
+    w.write_all(&[32, 0x28])?;
+    let mut out = Vec::new();
+    for row in b.pixels.chunks(b.w as usize) {
+        let mut i = 0;
+        while i < row.len() {
+            let mut n = 1;
+            while i + n < row.len() && n < 128 && row[i + n] == row[i] {
+                n += 1;
+            }
+            if n > 1 {
+                let [r, g, b, a] = rgba(b.fmt.load(row[i]));
+                out.extend_from_slice(&[0x80 | (n - 1) as u8, b, g, r, a]);
+            } else {
+                /* Gather a raw packet up to the start of the next run */
+                while i + n < row.len() && n < 128
+                    && (i + n + 1 >= row.len() || row[i + n] != row[i + n + 1])
+                {
+                    n += 1;
+                }
+                out.push((n - 1) as u8);
+                for p in &row[i..(i + n)] {
+                    let [r, g, b, a] = rgba(b.fmt.load(*p));
+                    out.extend_from_slice(&[b, g, r, a]);
+                }
+            }
+            i += n;
+        }
+    }
+    w.write_all(&out)
+}
@@ -0,0 +1,100 @@
+use super::*;
+
+/* Largest difference between any two channels of `a` and `b` */
+fn distance(a: Pixel, b: Pixel) -> u8 {
+    let (a, b) = unsafe { (a.rgba, b.rgba) };
+    a.r.abs_diff(b.r)
+        .max(a.g.abs_diff(b.g))
+        .max(a.b.abs_diff(b.b))
+        .max(a.a.abs_diff(b.a))
+}
+
+/* Returns the spans, as (`y`, `x0`, `x1`) with `x1` exclusive, of the region
+ * around (`x`, `y`) whose pixels are within `opts.tolerance` of the one at
+ * (`x`, `y`), staying within the clip rect. Uses an explicit stack of seeds so
+ * large regions don't overflow the call stack */
+pub fn region<S: Pixels>(b: &Buffer<S>, x: i32, y: i32, opts: &FloodFill) -> Vec<(i32, i32, i32)> {
+    let clip = b.clip;
+    let mut spans = Vec::new();
+    if x < clip.x || y < clip.y || x >= clip.x + clip.w || y >= clip.y + clip.h {
+        return spans;
+    }
+    let load = |x: i32, y: i32| b.fmt.load(b.pixels[(x + y * b.pitch) as usize]);
+    let target = load(x, y);
+    let mut visited = vec![false; (clip.w * clip.h) as usize];
+    let idx = |x: i32, y: i32| ((x - clip.x) + (y - clip.y) * clip.w) as usize;
+    let matches = |visited: &[bool], x: i32, y: i32| {
+        !visited[idx(x, y)] && distance(load(x, y), target) <= opts.tolerance
+    };
+    /* Diagonal neighbours extend the rows above and below by one pixel */
+    let reach = match opts.connectivity {
+        Connectivity::FOUR => 0,
+        Connectivity::EIGHT => 1,
+    };
+    let mut stack = vec![(x, y)];
+    while let Some((x, y)) = stack.pop() {
+        if !matches(&visited, x, y) {
+            continue;
+        }
+        let (mut x0, mut x1) = (x, x + 1);
+        while x0 > clip.x && matches(&visited, x0 - 1, y) {
+            x0 -= 1;
+        }
+        while x1 < clip.x + clip.w && matches(&visited, x1, y) {
+            x1 += 1;
+        }
+        for px in x0..x1 {
+            visited[idx(px, y)] = true;
+        }
+        spans.push((y, x0, x1));
+        /* Seed the start of every matching run next to the span */
+        let (lo, hi) = ((x0 - reach).max(clip.x), (x1 + reach).min(clip.x + clip.w));
+        for &ny in &[y - 1, y + 1] {
+            if ny < clip.y || ny >= clip.y + clip.h {
+                continue;
+            }
+            let mut run = false;
+            for px in lo..hi {
+                let m = matches(&visited, px, ny);
+                if m && !run {
+                    stack.push((px, ny));
+                }
+                run = m;
+            }
+        }
+    }
+    spans
+}
+
+/* Fills the region around (`x`, `y`) with `c`, either overwriting its pixels
+ * or blending through the draw mode */
+pub fn flood<S: PixelsMut>(b: &mut Buffer<S>, c: Pixel, x: i32, y: i32, opts: &FloodFill) {
+    let spans = region(b, x, y, opts);
+    let paint = Paint::Solid(c);
+    let stored = b.fmt.store(c);
+    for (y, x0, x1) in spans {
+        if opts.blend {
+            paint::span(b, &paint, y, x0, x1);
+        } else {
+            let row = (y * b.pitch) as usize;
+            for p in &mut b.pixels[row + x0 as usize..row + x1 as usize] {
+                *p = stored;
+            }
+        }
+    }
+}
+
+/* Returns a buffer the size of `b` that is opaque white where the region
+ * around (`x`, `y`) is and transparent everywhere else */
+pub fn select<S: Pixels>(b: &Buffer<S>, x: i32, y: i32, opts: &FloodFill) -> Buffer {
+    let mut mask = Buffer::new(b.w, b.h);
+    mask.clear(Pixel::pixel(0, 0, 0, 0));
+    let selected = Pixel::color(0xff, 0xff, 0xff);
+    for (y, x0, x1) in region(b, x, y, opts) {
+        let row = (y * mask.pitch) as usize;
+        for p in &mut mask.pixels[row + x0 as usize..row + x1 as usize] {
+            *p = selected;
+        }
+    }
+    mask
+}
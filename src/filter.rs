@@ -0,0 +1,62 @@
+use super::*;
+
+fn linear(x: f32) -> f32 {
+    (1f32 - x.abs()).max(0f32)
+}
+
+/* Catmull-Rom spline */
+fn cubic(x: f32) -> f32 {
+    let x = x.abs();
+    if x < 1f32 {
+        (1.5 * x - 2.5) * x * x + 1f32
+    } else if x < 2f32 {
+        ((-0.5 * x + 2.5) * x - 4f32) * x + 2f32
+    } else {
+        0f32
+    }
+}
+
+/* Returns the color of `src` at (`fx`, `fy`), given in `FX_BITS_12` fixed
+ * point, reading only pixels within `sub`. Colors are weighted by their alpha
+ * so transparent pixels don't darken the edges of a shape */
+pub fn sample<S: Pixels>(src: &Buffer<S>, sub: &Rect, fx: i32, fy: i32, filter: Filter) -> Pixel {
+    let load = |x: i32, y: i32| {
+        let x = x.clamp(sub.x, sub.x + sub.w - 1);
+        let y = y.clamp(sub.y, sub.y + sub.h - 1);
+        src.fmt.load(src.pixels[(x + y * src.pitch) as usize])
+    };
+    /* Taps on either side of the sampled point, and their weights */
+    let (taps, kernel): (i32, fn(f32) -> f32) = match filter {
+        Filter::NEAREST => return load(fx >> FX_BITS_12, fy >> FX_BITS_12),
+        Filter::BILINEAR => (1, linear),
+        Filter::BICUBIC => (2, cubic),
+    };
+    /* Shift to pixel centers so the fraction measures the distance from one */
+    let (u, v) = (fx - (FX_UNIT_12 / 2) as i32, fy - (FX_UNIT_12 / 2) as i32);
+    let (x0, y0) = (u >> FX_BITS_12, v >> FX_BITS_12);
+    let tx = (u & FX_MASK_12 as i32) as f32 / FX_UNIT_12 as f32;
+    let ty = (v & FX_MASK_12 as i32) as f32 / FX_UNIT_12 as f32;
+    let mut acc = [0f32; 4];
+    for j in (1 - taps)..(taps + 1) {
+        let wy = kernel(j as f32 - ty);
+        for i in (1 - taps)..(taps + 1) {
+            let p = load(x0 + i, y0 + j);
+            let (r, g, b, a) = unsafe { (p.rgba.r, p.rgba.g, p.rgba.b, p.rgba.a) };
+            let w = wy * kernel(i as f32 - tx) * f32::from(a);
+            acc[0] += f32::from(r) * w;
+            acc[1] += f32::from(g) * w;
+            acc[2] += f32::from(b) * w;
+            acc[3] += w;
+        }
+    }
+    if acc[3] < 0.5 {
+        return Pixel::pixel(0, 0, 0, 0);
+    }
+    let channel = |v: f32| (v / acc[3] + 0.5).clamp(0f32, 255f32) as u8;
+    Pixel::pixel(
+        channel(acc[0]),
+        channel(acc[1]),
+        channel(acc[2]),
+        (acc[3] + 0.5).clamp(0f32, 255f32) as u8,
+    )
+}
@@ -6,26 +6,38 @@ extern crate uluru;
 
 #[macro_use]
 mod macros;
+mod aa;
 mod copy;
 mod draw;
+mod encode;
+mod fill;
+mod filter;
+mod paint;
+mod path;
+mod raster;
 mod util;
 
 /*
 https://github.com/redox-os/rusttype/issues/61
 */
 
-use std::path::Path;
-use std::ops::{Add, Div, Mul, Neg, Sub};
-use std::{fmt, mem, slice, f32};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::ops::{Add, Deref, DerefMut, Div, Mul, Neg, Sub};
+use std::{error, fmt, mem, f32};
 
 use stb_image::image;
 use rusttype::{point, FontCollection, PositionedGlyph, Scale};
 
 use util::*;
 
+pub use paint::{Gradient, Paint, Pattern, Spread};
+pub use path::Path;
+pub use util::Point;
+
 const FX_BITS_12: u32 = 12;
 const FX_UNIT_12: u32 = 1 << FX_BITS_12;
-// const FX_MASK_12: u32 = FX_UNIT_12 - 1;
+const FX_MASK_12: u32 = FX_UNIT_12 - 1;
 
 const FX_BITS_10: u32 = 10;
 const FX_UNIT_10: u32 = 1 << FX_BITS_10;
@@ -36,18 +48,6 @@ const PI2: f32 = ::std::f32::consts::PI * 2f32;
 const DEFAULT_FONT_DATA: &[u8] = include_bytes!("embed/default.ttf");
 const DEFAULT_FONT_SIZE: f32 = 16.0;
 
-#[cfg(feature = "MODE_RGBA")]
-const RGB_MASK: u32 = 0xff_ffff;
-
-#[cfg(feature = "MODE_ARGB")]
-const RGB_MASK: u32 = 0xffffff00;
-
-#[cfg(feature = "MODE_ABGR")]
-const RGB_MASK: u32 = 0xffffff00;
-
-#[cfg(any(feature = "MODE_BGRA",
-          all(not(feature = "MODE_RGBA"), not(feature = "MODE_ARGB"),
-              not(feature = "MODE_ABGR"))))]
 const RGB_MASK: u32 = 0xff_ffff;
 
 lazy_static! {
@@ -75,6 +75,88 @@ fn fxsin(n: i32) -> i32 {
     SIN_TABLE[(n & FX_MASK_10 as i32) as usize]
 }
 
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    UnsupportedFormat,
+    Decode(String),
+    HdrImage,
+    InvalidFont,
+    EmptyFontCollection,
+    InvalidSize { w: i32, h: i32 },
+    SubRectOutOfBounds { sub: Rect, w: i32, h: i32 },
+    SizeMismatch { expected: (i32, i32), found: (i32, i32) },
+    EmptyPalette,
+    InvalidPitch { pitch: i32, w: i32 },
+    SliceTooSmall { expected: usize, found: usize },
+    UnalignedSlice,
+    IndexOutOfBounds { index: usize, len: usize },
+    InvalidSlice { slice: NineSlice, w: i32, h: i32 },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "i/o error: {}", e),
+            Error::UnsupportedFormat => write!(f, "unsupported image format"),
+            Error::Decode(ref e) => write!(f, "failed to decode image: {}", e),
+            Error::HdrImage => write!(f, "expected an 8-bit image, got an HDR image"),
+            Error::InvalidFont => write!(f, "invalid font data"),
+            Error::EmptyFontCollection => write!(f, "font collection contains no fonts"),
+            Error::InvalidSize { w, h } => write!(
+                f,
+                "invalid buffer size {}x{}, expected width and height of 1 or greater",
+                w, h
+            ),
+            Error::SubRectOutOfBounds { sub, w, h } => write!(
+                f,
+                "sub rectangle {}x{} at ({}, {}) out of bounds of {}x{} buffer",
+                sub.w, sub.h, sub.x, sub.y, w, h
+            ),
+            Error::SizeMismatch { expected, found } => write!(
+                f,
+                "expected buffer size {}x{}, found {}x{}",
+                expected.0, expected.1, found.0, found.1
+            ),
+            Error::EmptyPalette => write!(f, "expected non-empty palette"),
+            Error::InvalidPitch { pitch, w } => write!(
+                f,
+                "invalid pitch of {} pixels, expected width of {} or greater",
+                pitch, w
+            ),
+            Error::SliceTooSmall { expected, found } => write!(
+                f,
+                "expected slice of {} elements or more, got {}",
+                expected, found
+            ),
+            Error::UnalignedSlice => write!(f, "expected slice and pitch aligned to 4 bytes"),
+            Error::IndexOutOfBounds { index, len } => {
+                write!(f, "vertex index {} out of bounds of {} vertices", index, len)
+            }
+            Error::InvalidSlice { slice, w, h } => write!(
+                f,
+                "nine-slice borders {}, {}, {}, {} don't fit within {}x{} source",
+                slice.left, slice.top, slice.right, slice.bottom, w, h
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum PixelFormat {
     BGRA,
@@ -83,6 +165,84 @@ pub enum PixelFormat {
     ABGR,
 }
 
+impl PixelFormat {
+    /* The layout of `Channel`, which every `Pixel` passed to or returned from
+     * the drawing API uses regardless of the format of a buffer's storage */
+    pub const NATIVE: PixelFormat = PixelFormat::BGRA;
+
+    /* Bit offsets of the r, g, b and a channels of a pixel packed into a u32 */
+    fn shifts(self) -> (u32, u32, u32, u32) {
+        match self {
+            PixelFormat::BGRA => (16, 8, 0, 24),
+            PixelFormat::RGBA => (0, 8, 16, 24),
+            PixelFormat::ARGB => (8, 16, 24, 0),
+            PixelFormat::ABGR => (24, 16, 8, 0),
+        }
+    }
+
+    /* Positions of the r, g, b and a bytes of a pixel in memory */
+    fn offsets(self) -> [usize; 4] {
+        match self {
+            PixelFormat::BGRA => [2, 1, 0, 3],
+            PixelFormat::RGBA => [0, 1, 2, 3],
+            PixelFormat::ARGB => [1, 2, 3, 0],
+            PixelFormat::ABGR => [3, 2, 1, 0],
+        }
+    }
+
+    /* Converts a pixel stored in this format to the native layout */
+    #[inline]
+    pub fn load(self, p: Pixel) -> Pixel {
+        if self == PixelFormat::NATIVE {
+            return p;
+        }
+        let b = unsafe { [p.rgba.b, p.rgba.g, p.rgba.r, p.rgba.a] };
+        let o = self.offsets();
+        Pixel::pixel(b[o[0]], b[o[1]], b[o[2]], b[o[3]])
+    }
+
+    /* Converts a pixel in the native layout to this format */
+    #[inline]
+    pub fn store(self, p: Pixel) -> Pixel {
+        if self == PixelFormat::NATIVE {
+            return p;
+        }
+        let mut b = [0; 4];
+        let o = self.offsets();
+        unsafe {
+            b[o[0]] = p.rgba.r;
+            b[o[1]] = p.rgba.g;
+            b[o[2]] = p.rgba.b;
+            b[o[3]] = p.rgba.a;
+        }
+        Pixel {
+            rgba: Channel {
+                b: b[0],
+                g: b[1],
+                r: b[2],
+                a: b[3],
+            },
+        }
+    }
+
+    /* Converts a pixel stored in this format to `to` */
+    #[inline]
+    pub fn convert(self, p: Pixel, to: PixelFormat) -> Pixel {
+        if self == to {
+            p
+        } else {
+            to.store(self.load(p))
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ImageFormat {
+    PNG,
+    BMP,
+    TGA,
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum BlendMode {
     ALPHA,
@@ -104,36 +264,7 @@ pub enum ColorChannel {
     A,
 }
 
-#[cfg(feature = "MODE_RGBA")]
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub struct Channel {
-    pub r: u8,
-    pub g: u8,
-    pub b: u8,
-    pub a: u8,
-}
-
-#[cfg(feature = "MODE_ARGB")]
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub struct Channel {
-    pub a: u8,
-    pub r: u8,
-    pub g: u8,
-    pub b: u8,
-}
-
-#[cfg(feature = "MODE_ABGR")]
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub struct Channel {
-    pub a: u8,
-    pub b: u8,
-    pub g: u8,
-    pub r: u8,
-}
-
-#[cfg(any(feature = "MODE_BGRA",
-          all(not(feature = "MODE_RGBA"), not(feature = "MODE_ARGB"),
-              not(feature = "MODE_ABGR"))))]
+#[repr(C)]
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Channel {
     pub b: u8,
@@ -196,6 +327,7 @@ impl_div!(Channel, |s: Channel, rhs: Channel| -> Channel {
     }
 });
 
+#[repr(C)]
 #[derive(Clone, Copy)]
 pub union Pixel {
     pub word: u32,
@@ -331,11 +463,23 @@ impl_neg!(Rect, |s: Rect| -> Rect {
     }
 });
 
+/* How scaled and rotated blits pick colors from the source */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Filter {
+    NEAREST,
+    BILINEAR,
+    BICUBIC,
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct DrawMode {
     pub color: Pixel,
     pub blend: BlendMode,
     pub alpha: u8,
+    /* Makes `draw_line`, `draw_circle` and `draw_ring` anti-aliased */
+    pub antialias: bool,
+    /* Used by `draw` and `copy_pixels` when scaling or rotating */
+    pub filter: Filter,
 }
 
 impl DrawMode {
@@ -344,6 +488,8 @@ impl DrawMode {
             color,
             blend,
             alpha,
+            antialias: false,
+            filter: Filter::NEAREST,
         }
     }
 }
@@ -363,6 +509,230 @@ impl Transform {
     }
 }
 
+/* Maps (`x`, `y`) to (`xx * x + xy * y + tx`, `yx * x + yy * y + ty`) */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Affine2 {
+    pub xx: f32,
+    pub xy: f32,
+    pub yx: f32,
+    pub yy: f32,
+    pub tx: f32,
+    pub ty: f32,
+}
+
+impl Affine2 {
+    pub fn new(xx: f32, xy: f32, yx: f32, yy: f32, tx: f32, ty: f32) -> Affine2 {
+        Affine2 { xx, xy, yx, yy, tx, ty }
+    }
+
+    pub fn identity() -> Affine2 {
+        Affine2::new(1f32, 0f32, 0f32, 1f32, 0f32, 0f32)
+    }
+
+    pub fn translate(tx: f32, ty: f32) -> Affine2 {
+        Affine2::new(1f32, 0f32, 0f32, 1f32, tx, ty)
+    }
+
+    pub fn scale(sx: f32, sy: f32) -> Affine2 {
+        Affine2::new(sx, 0f32, 0f32, sy, 0f32, 0f32)
+    }
+
+    /* Rotates clockwise by `r` radians, as `Transform` does */
+    pub fn rotate(r: f32) -> Affine2 {
+        let (sin, cos) = r.sin_cos();
+        Affine2::new(cos, -sin, sin, cos, 0f32, 0f32)
+    }
+
+    /* Shears x by `kx` times y and y by `ky` times x */
+    pub fn skew(kx: f32, ky: f32) -> Affine2 {
+        Affine2::new(1f32, kx, ky, 1f32, 0f32, 0f32)
+    }
+
+    /* Returns the transform applying `other` first and then `self`, so a
+     * child's transform composes onto its parent's as `parent.compose(child)` */
+    pub fn compose(&self, other: Affine2) -> Affine2 {
+        let (a, b) = (self, other);
+        Affine2 {
+            xx: a.xx * b.xx + a.xy * b.yx,
+            xy: a.xx * b.xy + a.xy * b.yy,
+            yx: a.yx * b.xx + a.yy * b.yx,
+            yy: a.yx * b.xy + a.yy * b.yy,
+            tx: a.xx * b.tx + a.xy * b.ty + a.tx,
+            ty: a.yx * b.tx + a.yy * b.ty + a.ty,
+        }
+    }
+
+    /* Returns the transform undoing this one, or `None` if it collapses the
+     * plane onto a line or point */
+    pub fn invert(&self) -> Option<Affine2> {
+        let det = self.xx * self.yy - self.xy * self.yx;
+        if det == 0f32 || !det.is_finite() {
+            return None;
+        }
+        let (xx, xy, yx, yy) = (self.yy / det, -self.xy / det, -self.yx / det, self.xx / det);
+        Some(Affine2 {
+            xx,
+            xy,
+            yx,
+            yy,
+            tx: -(xx * self.tx + xy * self.ty),
+            ty: -(yx * self.tx + yy * self.ty),
+        })
+    }
+
+    pub fn apply(&self, x: f32, y: f32) -> (f32, f32) {
+        (
+            self.xx * x + self.xy * y + self.tx,
+            self.yx * x + self.yy * y + self.ty,
+        )
+    }
+}
+
+/* The placement `Buffer::draw` gives a transformed source relative to the
+ * position it is drawn at */
+impl From<Transform> for Affine2 {
+    fn from(t: Transform) -> Affine2 {
+        Affine2::rotate(t.r)
+            .compose(Affine2::scale(t.sx, t.sy))
+            .compose(Affine2::translate(-t.ox, -t.oy))
+    }
+}
+
+/* How `Buffer::draw_nine_slice` cuts up its source: the borders, in pixels,
+ * keep their size while the edges and center between them fill the rest */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NineSlice {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+    /* Repeat the edges and center instead of stretching them */
+    pub tile: bool,
+}
+
+impl NineSlice {
+    pub fn new(left: i32, top: i32, right: i32, bottom: i32) -> NineSlice {
+        NineSlice {
+            left,
+            top,
+            right,
+            bottom,
+            tile: false,
+        }
+    }
+}
+
+/* How texture coordinates are interpolated across a triangle */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TextureMapping {
+    /* Linearly in screen space, which is cheaper and exact for flat 2D
+     * meshes */
+    AFFINE,
+    /* Accounting for each vertex's `w`, so textures on triangles receding
+     * into the distance don't warp */
+    PERSPECTIVE,
+}
+
+/* A corner of a triangle drawn with `Buffer::draw_triangles` */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Vertex {
+    pub x: f32,
+    pub y: f32,
+    /* Position on the source in pixels, from its top left corner */
+    pub u: f32,
+    pub v: f32,
+    /* Depth of the vertex as the homogeneous `w` left over from projecting
+     * it, 1 for flat meshes. Perspective mapping skips triangles with a `w` of
+     * 0 or less */
+    pub w: f32,
+    /* Multiplies the source colors, white leaves them untouched */
+    pub color: Pixel,
+}
+
+impl Vertex {
+    pub fn new(x: f32, y: f32, u: f32, v: f32, color: Pixel) -> Vertex {
+        Vertex {
+            x,
+            y,
+            u,
+            v,
+            w: 1f32,
+            color,
+        }
+    }
+}
+
+/* Decides which parts of a self-intersecting or nested shape are filled */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FillRule {
+    EVENODD,
+    NONZERO,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LineCap {
+    BUTT,
+    ROUND,
+    SQUARE,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LineJoin {
+    MITER,
+    ROUND,
+    BEVEL,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LineStyle {
+    pub width: f32,
+    pub cap: LineCap,
+    pub join: LineJoin,
+    /* Miter joins longer than this many times the width fall back to bevel */
+    pub miter_limit: f32,
+}
+
+impl LineStyle {
+    pub fn new(width: f32, cap: LineCap, join: LineJoin) -> LineStyle {
+        LineStyle {
+            width,
+            cap,
+            join,
+            miter_limit: 4f32,
+        }
+    }
+}
+
+/* Which neighbours of a pixel a flood fill spreads to */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Connectivity {
+    FOUR,
+    EIGHT,
+}
+
+/* Used by `flood_fill` and `select_region`, so a selection always matches
+ * the fill from the same point */
+const DEFAULT_CONNECTIVITY: Connectivity = Connectivity::EIGHT;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FloodFill {
+    /* Largest per-channel difference from the starting pixel still filled */
+    pub tolerance: u8,
+    pub connectivity: Connectivity,
+    /* Blend through the draw mode instead of overwriting the pixels */
+    pub blend: bool,
+}
+
+impl FloodFill {
+    pub fn new(tolerance: u8, connectivity: Connectivity) -> FloodFill {
+        FloodFill {
+            tolerance,
+            connectivity,
+            blend: false,
+        }
+    }
+}
+
 impl_add!(Transform, |s: Transform, rhs: Transform| -> Transform {
     Transform {
         ox: s.ox + rhs.ox,
@@ -418,67 +788,192 @@ impl_neg!(Transform, |s: Transform| -> Transform {
 //
 // }
 
+/* Pixel storage a `Buffer` can read from; implemented for `Vec<Pixel>` and
+ * borrowed slices */
+pub trait Pixels: Deref<Target = [Pixel]> {}
+
+impl<T: Deref<Target = [Pixel]>> Pixels for T {}
+
+/* Pixel storage a `Buffer` can draw into */
+pub trait PixelsMut: Pixels + DerefMut {}
+
+impl<T: Pixels + DerefMut> PixelsMut for T {}
+
 #[derive(Debug, Clone, PartialEq)]
-pub struct Buffer {
+pub struct Buffer<S = Vec<Pixel>> {
     pub mode: DrawMode,
     pub clip: Rect,
-    pub pixels: Vec<Pixel>,
+    /* Stored in `fmt`; use `get_pixel`/`set_pixel` or `PixelFormat::load` and
+     * `PixelFormat::store` to work with native pixels */
+    pub pixels: S,
+    pub fmt: PixelFormat,
     pub w: i32,
     pub h: i32,
+    /* Distance in pixels between the starts of two rows; equal to `w` for
+     * owned buffers */
+    pub pitch: i32,
+    /* Coverage of the pushed clip masks, `w` by `h`, each one already
+     * intersected with those below it */
+    masks: Vec<Vec<u8>>,
+    states: Vec<State>,
+}
+
+/* Drawing state kept by `Buffer::save_state` */
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct State {
+    mode: DrawMode,
+    clip: Rect,
+    masks: usize,
+}
+
+/* A read-only buffer over borrowed pixels, usable as a drawing source */
+pub type BufferView<'a> = Buffer<&'a [Pixel]>;
+
+/* A buffer drawing into borrowed pixels, such as a locked texture or a
+ * region of another buffer */
+pub type BufferViewMut<'a> = Buffer<&'a mut [Pixel]>;
+
+/* Checks that `len` pixels can hold `h` rows of `w` pixels spaced `pitch`
+ * pixels apart */
+fn check_view_size(len: usize, w: i32, h: i32, pitch: i32) -> Result<(), Error> {
+    if w < 1 || h < 1 {
+        return Err(Error::InvalidSize { w, h });
+    }
+    if pitch < w {
+        return Err(Error::InvalidPitch { pitch, w });
+    }
+    let expected = (pitch as usize) * (h as usize - 1) + w as usize;
+    if len < expected {
+        return Err(Error::SliceTooSmall {
+            expected,
+            found: len,
+        });
+    }
+    Ok(())
+}
+
+impl<S: Pixels> Buffer<S> {
+    fn with_pixels(pixels: S, w: i32, h: i32, pitch: i32, fmt: PixelFormat) -> Buffer<S> {
+        Buffer {
+            w,
+            h,
+            pitch,
+            clip: Rect::new(0, 0, w, h),
+            pixels,
+            fmt,
+            mode: DrawMode::new(Pixel { word: RGB_MASK }, BlendMode::ALPHA, 0xff),
+            masks: Vec::new(),
+            states: Vec::new(),
+        }
+    }
+}
+
+impl<'a> BufferView<'a> {
+    pub fn from_slice(
+        pixels: &'a [Pixel],
+        w: i32,
+        h: i32,
+        pitch: i32,
+        fmt: PixelFormat,
+    ) -> Result<BufferView<'a>, Error> {
+        check_view_size(pixels.len(), w, h, pitch)?;
+        Ok(Buffer::with_pixels(pixels, w, h, pitch, fmt))
+    }
+}
+
+impl<'a> BufferViewMut<'a> {
+    pub fn from_slice_mut(
+        pixels: &'a mut [Pixel],
+        w: i32,
+        h: i32,
+        pitch: i32,
+        fmt: PixelFormat,
+    ) -> Result<BufferViewMut<'a>, Error> {
+        check_view_size(pixels.len(), w, h, pitch)?;
+        Ok(Buffer::with_pixels(pixels, w, h, pitch, fmt))
+    }
+
+    /* Views raw bytes such as a locked texture or framebuffer; `pitch` is in
+     * bytes and must be a multiple of 4 */
+    pub fn from_bytes_mut(
+        bytes: &'a mut [u8],
+        w: i32,
+        h: i32,
+        pitch: usize,
+        fmt: PixelFormat,
+    ) -> Result<BufferViewMut<'a>, Error> {
+        /* Every bit pattern is a valid `Pixel`, only alignment matters */
+        let (head, pixels, _) = unsafe { bytes.align_to_mut::<Pixel>() };
+        if !head.is_empty() || !pitch.is_multiple_of(mem::size_of::<Pixel>()) {
+            return Err(Error::UnalignedSlice);
+        }
+        BufferViewMut::from_slice_mut(pixels, w, h, (pitch / 4) as i32, fmt)
+    }
 }
 
 impl Buffer {
     pub fn new(w: i32, h: i32) -> Buffer {
-        if w < 1 {
-            panic!("expected width of 1 or greater")
-        }
-        if h < 1 {
-            panic!("expected height of 1 or greater")
+        Buffer::try_new(w, h).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_new(w: i32, h: i32) -> Result<Buffer, Error> {
+        if w < 1 || h < 1 {
+            return Err(Error::InvalidSize { w, h });
         }
         let black = Pixel::color(0, 0, 0);
         let mut buf = Buffer {
             w,
             h,
+            pitch: w,
             clip: Rect::new(0, 0, w, h),
             pixels: vec![black; (w * h) as usize],
+            fmt: PixelFormat::NATIVE,
             mode: DrawMode::new(black, BlendMode::ALPHA, 0xff),
+            masks: Vec::new(),
+            states: Vec::new(),
         };
         buf.reset();
-        buf
+        Ok(buf)
     }
 
-    pub fn file<T: AsRef<Path>>(file: T) -> Option<Buffer> {
-        let res = image::load_with_depth(file, 4, false);
-        if let image::LoadResult::ImageU8(img) = res {
-            unsafe {
-                let mut buf = Buffer::new(img.width as i32, img.height as i32);
-                let data = slice::from_raw_parts(
-                    mem::transmute(img.data.as_ptr()),
-                    mem::size_of::<u8>() * img.data.len(),
-                );
-                buf.load_pixels(data, PixelFormat::RGBA);
-                return Some(buf);
-            }
+    pub fn file<T: AsRef<std::path::Path>>(file: T) -> Result<Buffer, Error> {
+        Buffer::bytes(fs::read(file)?)
+    }
+
+    pub fn bytes<T: AsRef<[u8]>>(bytes: T) -> Result<Buffer, Error> {
+        let bytes = bytes.as_ref();
+        if !is_known_image(bytes) {
+            return Err(Error::UnsupportedFormat);
         }
-        None
-    }
-
-    pub fn bytes<T: AsRef<[u8]>>(bytes: T) -> Option<Buffer> {
-        let res = image::load_from_memory_with_depth(bytes.as_ref(), 4, false);
-        if let image::LoadResult::ImageU8(img) = res {
-            unsafe {
-                let mut buf = Buffer::new(img.width as i32, img.height as i32);
-                buf.load_pixels(
-                    slice::from_raw_parts_mut(
-                        mem::transmute(img.data.as_ptr()),
-                        mem::size_of::<T>() * img.data.len(),
-                    ),
-                    PixelFormat::RGBA,
-                );
-                return Some(buf);
+        match image::load_from_memory_with_depth(bytes, 4, false) {
+            image::LoadResult::ImageU8(img) => {
+                let mut buf = Buffer::try_new(img.width as i32, img.height as i32)?;
+                let data: Vec<u32> = img.data
+                    .chunks(4)
+                    .map(|p| {
+                        tu32!(p[0]) | tu32!(p[1]) << 8 | tu32!(p[2]) << 16 | tu32!(p[3]) << 24
+                    })
+                    .collect();
+                buf.load_pixels(&data, PixelFormat::RGBA);
+                Ok(buf)
             }
+            image::LoadResult::ImageF32(_) => Err(Error::HdrImage),
+            image::LoadResult::Error(e) => Err(Error::Decode(e)),
+        }
+    }
+
+    pub fn save<T: AsRef<std::path::Path>>(&self, file: T, fmt: ImageFormat) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(file)?);
+        self.encode(&mut w, fmt)?;
+        w.flush()
+    }
+
+    pub fn encode<W: Write>(&self, mut w: W, fmt: ImageFormat) -> io::Result<()> {
+        match fmt {
+            ImageFormat::PNG => encode::png(self, &mut w),
+            ImageFormat::BMP => encode::bmp(self, &mut w),
+            ImageFormat::TGA => encode::tga(self, &mut w),
         }
-        None
     }
 
     // pub fn clone(&mut self) -> Buffer {
@@ -491,114 +986,417 @@ impl Buffer {
     pub fn resize(&mut self, w: i32, h: i32) {
         self.w = w;
         self.h = h;
-        self.pixels.resize((w * h) as usize, Pixel::color(0, 0, 0));
+        self.pitch = w;
+        let black = self.fmt.store(Pixel::color(0, 0, 0));
+        self.pixels.resize((w * h) as usize, black);
         self.clip = Rect::new(0, 0, self.w, self.h);
+        /* Clip masks and saved clip rects were made for the old size */
+        self.masks.clear();
+        self.states.clear();
     }
 
     pub fn load_pixels(&mut self, src: &[u32], fmt: PixelFormat) {
-        let (sr, sg, sb, sa) = match fmt {
-            PixelFormat::BGRA => (16, 8, 0, 24),
-            PixelFormat::RGBA => (0, 8, 16, 24),
-            PixelFormat::ARGB => (8, 16, 24, 0),
-            PixelFormat::ABGR => (24, 16, 8, 0),
-        };
-        unsafe {
-            for i in 0..(self.w * self.h) as usize {
-                self.pixels[i].rgba.r = ((src[i] >> sr) & 0xff) as u8;
-                self.pixels[i].rgba.g = ((src[i] >> sg) & 0xff) as u8;
-                self.pixels[i].rgba.b = ((src[i] >> sb) & 0xff) as u8;
-                self.pixels[i].rgba.a = ((src[i] >> sa) & 0xff) as u8;
+        let n = (self.w * self.h) as usize;
+        /* Formats match? no need to touch the individual channels */
+        if fmt == self.fmt {
+            for (d, s) in self.pixels[..n].iter_mut().zip(&src[..n]) {
+                d.word = *s;
             }
+            return;
         }
-    }
-
-    pub fn load_pixels8(&mut self, src: &[u8], pal: Option<&[Pixel]>) {
-        for i in (0..(self.w * self.h) as usize).rev() {
-            self.pixels[i] = match pal {
-                Some(pal) => pal[src[i] as usize],
-                None => Pixel::pixel(0xff, 0xff, 0xff, src[i]),
-            };
+        let (sr, sg, sb, sa) = fmt.shifts();
+        let to = self.fmt;
+        for (d, s) in self.pixels[..n].iter_mut().zip(&src[..n]) {
+            *d = to.store(Pixel::pixel(
+                ((s >> sr) & 0xff) as u8,
+                ((s >> sg) & 0xff) as u8,
+                ((s >> sb) & 0xff) as u8,
+                ((s >> sa) & 0xff) as u8,
+            ));
         }
     }
 
-    pub fn set_blend(&mut self, blend: BlendMode) {
-        self.mode.blend = blend;
-    }
-
-    pub fn set_alpha(&mut self, alpha: u8) {
-        self.mode.alpha = alpha;
-    }
-
-    pub fn set_color(&mut self, c: Pixel) {
-        self.mode.color.word = unsafe { c.word & RGB_MASK };
+    pub fn store_pixels(&self, dst: &mut [u32], fmt: PixelFormat) {
+        self.try_store_pixels(dst, fmt)
+            .unwrap_or_else(|e| panic!("{}", e))
     }
 
-    pub fn set_clip(&mut self, r: Rect) {
-        self.clip = r;
-        let r = Rect {
-            x: 0,
-            y: 0,
-            w: self.w,
-            h: self.h,
-        };
-        clip_rect(&mut self.clip, &r);
+    pub fn try_store_pixels(&self, dst: &mut [u32], fmt: PixelFormat) -> Result<(), Error> {
+        let n = (self.w * self.h) as usize;
+        if dst.len() < n {
+            return Err(Error::SliceTooSmall {
+                expected: n,
+                found: dst.len(),
+            });
+        }
+        /* Formats match? no need to touch the individual channels */
+        if fmt == self.fmt {
+            for (d, s) in dst[..n].iter_mut().zip(&self.pixels[..n]) {
+                *d = unsafe { s.word };
+            }
+            return Ok(());
+        }
+        let (sr, sg, sb, sa) = fmt.shifts();
+        for (d, s) in dst[..n].iter_mut().zip(&self.pixels[..n]) {
+            let p = self.fmt.load(*s);
+            *d = unsafe {
+                tu32!(p.rgba.r) << sr | tu32!(p.rgba.g) << sg | tu32!(p.rgba.b) << sb
+                    | tu32!(p.rgba.a) << sa
+            };
+        }
+        Ok(())
+    }
+
+    /* Writes the pixels as bytes in `fmt`'s memory order, starting each row
+     * `pitch` bytes after the previous one */
+    pub fn store_bytes(&self, dst: &mut [u8], fmt: PixelFormat, pitch: usize) {
+        self.try_store_bytes(dst, fmt, pitch)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_store_bytes(
+        &self,
+        dst: &mut [u8],
+        fmt: PixelFormat,
+        pitch: usize,
+    ) -> Result<(), Error> {
+        let row = self.w as usize * 4;
+        if pitch < row {
+            /* Whole pixels that fit in the pitch, short of the width exactly
+             * when the pitch is short of the row */
+            return Err(Error::InvalidPitch {
+                pitch: (pitch / 4) as i32,
+                w: self.w,
+            });
+        }
+        let len = pitch * (self.h as usize - 1) + row;
+        if dst.len() < len {
+            return Err(Error::SliceTooSmall {
+                expected: len,
+                found: dst.len(),
+            });
+        }
+        let o = fmt.offsets();
+        for (src, d) in self.pixels.chunks(self.w as usize).zip(dst.chunks_mut(pitch)) {
+            for (s, d) in src.iter().zip(d[..row].chunks_mut(4)) {
+                let p = self.fmt.load(*s);
+                unsafe {
+                    d[o[0]] = p.rgba.r;
+                    d[o[1]] = p.rgba.g;
+                    d[o[2]] = p.rgba.b;
+                    d[o[3]] = p.rgba.a;
+                }
+            }
+        }
+        Ok(())
     }
 
-    pub fn reset(&mut self) {
-        self.set_blend(BlendMode::ALPHA);
-        self.set_alpha(0xff);
-        self.set_color(Pixel::color(0xff, 0xff, 0xff));
-        let (w, h) = (self.w, self.h);
-        self.set_clip(Rect { x: 0, y: 0, w, h });
+    pub fn load_pixels8(&mut self, src: &[u8], pal: Option<&[Pixel]>) {
+        for i in (0..(self.w * self.h) as usize).rev() {
+            self.pixels[i] = self.fmt.store(match pal {
+                Some(pal) => pal[src[i] as usize],
+                None => Pixel::pixel(0xff, 0xff, 0xff, src[i]),
+            });
+        }
     }
 
-    pub fn clear(&mut self, c: Pixel) {
-        self.pixels = vec![c; (self.w * self.h) as usize];
+    /* Converts the buffer's pixels to `fmt` in place */
+    pub fn set_format(&mut self, fmt: PixelFormat) {
+        if fmt == self.fmt {
+            return;
+        }
+        for p in &mut self.pixels {
+            *p = self.fmt.convert(*p, fmt);
+        }
+        self.fmt = fmt;
     }
+}
 
+impl<S: Pixels> Buffer<S> {
     pub fn get_size(&self) -> (i32, i32) {
         (self.w, self.h)
     }
 
     pub fn get_pixel(&self, x: i32, y: i32) -> Pixel {
         if x >= 0 && y >= 0 && x < self.w && y < self.h {
-            return self.pixels[(x + y * self.w) as usize];
+            return self.fmt.load(self.pixels[(x + y * self.pitch) as usize]);
         }
         Pixel { word: 0 }
     }
 
-    pub fn set_pixel(&mut self, c: Pixel, x: i32, y: i32) {
-        if x >= 0 && y >= 0 && x < self.w && y < self.h {
-            self.pixels[(x + y * self.w) as usize] = c;
-        }
+    /* Validates a sub rectangle of the buffer, returning `None` if it is empty and
+     * there is nothing to draw */
+    fn check_sub_rect(&self, sub: Option<Rect>) -> Result<Option<Rect>, Error> {
+        match sub {
+            Some(s) => {
+                if s.w <= 0 || s.h <= 0 {
+                    Ok(None)
+                } else if !(s.x >= 0 && s.y >= 0 && s.x + s.w <= self.w && s.y + s.h <= self.h) {
+                    Err(Error::SubRectOutOfBounds {
+                        sub: s,
+                        w: self.w,
+                        h: self.h,
+                    })
+                } else {
+                    Ok(Some(s))
+                }
+            }
+            None => Ok(Some(Rect::new(0, 0, self.w, self.h))),
+        }
+    }
+
+    /* Returns the slice range covering `r`, which must lie within the buffer */
+    fn sub_range(&self, r: Rect) -> Result<(usize, usize), Error> {
+        if r.w < 1 || r.h < 1 {
+            return Err(Error::InvalidSize { w: r.w, h: r.h });
+        }
+        if !(r.x >= 0 && r.y >= 0 && r.x + r.w <= self.w && r.y + r.h <= self.h) {
+            return Err(Error::SubRectOutOfBounds {
+                sub: r,
+                w: self.w,
+                h: self.h,
+            });
+        }
+        let start = (r.x + r.y * self.pitch) as usize;
+        Ok((start, start + ((r.h - 1) * self.pitch + r.w) as usize))
+    }
+
+    pub fn view<'a>(&'a self) -> BufferView<'a> {
+        let mut v = Buffer::with_pixels(&self.pixels[..], self.w, self.h, self.pitch, self.fmt);
+        v.mode = self.mode;
+        v
+    }
+
+    pub fn sub_view<'a>(&'a self, r: Rect) -> Result<BufferView<'a>, Error> {
+        let (start, end) = self.sub_range(r)?;
+        let mut v = Buffer::with_pixels(&self.pixels[start..end], r.w, r.h, self.pitch, self.fmt);
+        v.mode = self.mode;
+        Ok(v)
+    }
+
+    /* Returns the pixels `flood_fill` would touch from (`x`, `y`) as a mask
+     * of the same size, opaque white where selected and transparent elsewhere.
+     * Pixels within `tolerance` of the starting one are selected, diagonals
+     * included */
+    pub fn select_region(&self, x: i32, y: i32, tolerance: u8) -> Buffer {
+        fill::select(self, x, y, &FloodFill::new(tolerance, DEFAULT_CONNECTIVITY))
+    }
+
+    /* Like `select_region`, configured by `opts`; `opts.blend` is ignored */
+    pub fn select_region_with(&self, x: i32, y: i32, opts: &FloodFill) -> Buffer {
+        fill::select(self, x, y, opts)
+    }
+}
+
+impl<S: PixelsMut> Buffer<S> {
+    pub fn view_mut<'a>(&'a mut self) -> BufferViewMut<'a> {
+        let (w, h, pitch, fmt, mode) = (self.w, self.h, self.pitch, self.fmt, self.mode);
+        let mut v = Buffer::with_pixels(&mut self.pixels[..], w, h, pitch, fmt);
+        v.mode = mode;
+        v
+    }
+
+    /* Returns a view drawing into `r`; coordinates in the view are relative to
+     * the top-left of `r` and drawing is clipped to it */
+    pub fn sub_view_mut<'a>(&'a mut self, r: Rect) -> Result<BufferViewMut<'a>, Error> {
+        let (start, end) = self.sub_range(r)?;
+        let (pitch, fmt, mode) = (self.pitch, self.fmt, self.mode);
+        let mut v = Buffer::with_pixels(&mut self.pixels[start..end], r.w, r.h, pitch, fmt);
+        v.mode = mode;
+        Ok(v)
+    }
+
+    pub fn set_blend(&mut self, blend: BlendMode) {
+        self.mode.blend = blend;
+    }
+
+    pub fn set_alpha(&mut self, alpha: u8) {
+        self.mode.alpha = alpha;
+    }
+
+    pub fn set_antialias(&mut self, antialias: bool) {
+        self.mode.antialias = antialias;
+    }
+
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.mode.filter = filter;
     }
 
-    pub fn copy_pixels(
+    pub fn set_color(&mut self, c: Pixel) {
+        self.mode.color.word = unsafe { c.word & RGB_MASK };
+    }
+
+    pub fn set_clip(&mut self, r: Rect) {
+        self.clip = r;
+        let r = Rect {
+            x: 0,
+            y: 0,
+            w: self.w,
+            h: self.h,
+        };
+        clip_rect(&mut self.clip, &r);
+    }
+
+    /* Narrows the clip rect to its overlap with `r` */
+    pub fn intersect_clip(&mut self, mut r: Rect) {
+        clip_rect(&mut r, &self.clip);
+        self.clip = r;
+    }
+
+    /* Remembers the draw mode, clip rect and clip masks so that
+     * `restore_state` can bring them back. Saves nest */
+    pub fn save_state(&mut self) {
+        self.states.push(State {
+            mode: self.mode,
+            clip: self.clip,
+            masks: self.masks.len(),
+        });
+    }
+
+    /* Returns to the state at the last `save_state`, popping any clip masks
+     * pushed since. Returns `false` if nothing was saved */
+    pub fn restore_state(&mut self) -> bool {
+        match self.states.pop() {
+            Some(s) => {
+                self.mode = s.mode;
+                self.clip = s.clip;
+                self.masks.truncate(s.masks);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.set_blend(BlendMode::ALPHA);
+        self.set_alpha(0xff);
+        self.set_antialias(false);
+        self.set_filter(Filter::NEAREST);
+        self.set_color(Pixel::color(0xff, 0xff, 0xff));
+        let (w, h) = (self.w, self.h);
+        self.set_clip(Rect { x: 0, y: 0, w, h });
+        self.masks.clear();
+    }
+
+    /* Limits drawing to where `mask` is set, in its alpha channel unless
+     * `channel` says otherwise. Partial coverage blends partially; masks
+     * pushed on top of another one only cover where both do. Pixels written
+     * without blending, as by `clear`, `set_pixel` or `copy_pixels`, and views
+     * of the buffer aren't masked */
+    pub fn push_clip_mask<T: Pixels>(&mut self, mask: &Buffer<T>, channel: Option<ColorChannel>) {
+        self.try_push_clip_mask(mask, channel)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_push_clip_mask<T: Pixels>(
         &mut self,
-        src: &Buffer,
+        mask: &Buffer<T>,
+        channel: Option<ColorChannel>,
+    ) -> Result<(), Error> {
+        if mask.w != self.w || mask.h != self.h {
+            return Err(Error::SizeMismatch {
+                expected: (self.w, self.h),
+                found: (mask.w, mask.h),
+            });
+        }
+        let channel = channel.unwrap_or(ColorChannel::A);
+        let mut cov = Vec::with_capacity((self.w * self.h) as usize);
+        for y in 0..mask.h {
+            for x in 0..mask.w {
+                let m = mask.fmt.load(mask.pixels[(x + y * mask.pitch) as usize]);
+                cov.push(unsafe {
+                    match channel {
+                        ColorChannel::R => m.rgba.r,
+                        ColorChannel::G => m.rgba.g,
+                        ColorChannel::B => m.rgba.b,
+                        ColorChannel::A => m.rgba.a,
+                    }
+                });
+            }
+        }
+        if let Some(top) = self.masks.last() {
+            for (c, t) in cov.iter_mut().zip(top) {
+                *c = ((u32::from(*c) * u32::from(*t) + 0x7f) / 0xff) as u8;
+            }
+        }
+        self.masks.push(cov);
+        Ok(())
+    }
+
+    /* Removes the last pushed clip mask, returning `false` if there was none
+     * or if it was pushed before the last `save_state`, which still owns it */
+    pub fn pop_clip_mask(&mut self) -> bool {
+        let saved = self.states.last().map_or(0, |s| s.masks);
+        if self.masks.len() <= saved {
+            return false;
+        }
+        self.masks.pop().is_some()
+    }
+
+    /* Blends `c` onto (`x`, `y`) with `mode`, weighted by the clip mask. The
+     * pixel must be within the clip rect */
+    fn blend_at(&mut self, mode: &DrawMode, c: Pixel, x: i32, y: i32) {
+        let i = (x + y * self.pitch) as usize;
+        let cov = match self.masks.last() {
+            Some(m) => m[(x + y * self.w) as usize],
+            None => 0xff,
+        };
+        if cov == 0xff {
+            blend_pixel(mode, self.fmt, &mut self.pixels[i], c);
+        } else if cov != 0 {
+            let mut m = *mode;
+            m.alpha = ((u32::from(m.alpha) * u32::from(cov) + 0x7f) / 0xff) as u8;
+            blend_pixel(&m, self.fmt, &mut self.pixels[i], c);
+        }
+    }
+
+    pub fn clear(&mut self, c: Pixel) {
+        let c = self.fmt.store(c);
+        for y in 0..self.h {
+            let row = (y * self.pitch) as usize;
+            for p in &mut self.pixels[row..row + self.w as usize] {
+                *p = c;
+            }
+        }
+    }
+
+    pub fn set_pixel(&mut self, c: Pixel, x: i32, y: i32) {
+        if x >= 0 && y >= 0 && x < self.w && y < self.h {
+            self.pixels[(x + y * self.pitch) as usize] = self.fmt.store(c);
+        }
+    }
+
+    pub fn copy_pixels<T: Pixels>(
+        &mut self,
+        src: &Buffer<T>,
         x: i32,
         y: i32,
         sub: Option<Rect>,
         sx: f32,
         sy: f32,
     ) {
+        self.try_copy_pixels(src, x, y, sub, sx, sy)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_copy_pixels<T: Pixels>(
+        &mut self,
+        src: &Buffer<T>,
+        x: i32,
+        y: i32,
+        sub: Option<Rect>,
+        sx: f32,
+        sy: f32,
+    ) -> Result<(), Error> {
         let sx = sx.abs();
         let sy = sy.abs();
         if sx == 0f32 || sy == 0f32 {
-            return;
+            return Ok(());
         }
         /* Check sub rectangle */
-        let s = match sub {
-            Some(_s) => {
-                if _s.w <= 0 || _s.h <= 0 {
-                    return;
-                }
-                if !(_s.x >= 0 && _s.y >= 0 && _s.x + _s.w <= src.w && _s.y + _s.h <= src.h) {
-                    panic!("sub rectangle out of bounds");
-                }
-                _s
-            }
-            None => Rect::new(0, 0, src.w, src.h),
+        let s = match src.check_sub_rect(sub)? {
+            Some(s) => s,
+            None => return Ok(()),
         };
         /* Dispatch */
         if (sx - 1f32).abs() < f32::EPSILON && (sy - 1f32).abs() < f32::EPSILON {
@@ -608,70 +1406,33 @@ impl Buffer {
             /* Scaled copy */
             copy::scaled(self, src, x, y, s, sx, sy);
         }
+        Ok(())
     }
 
-    pub fn noise(&mut self, seed: u32, low: u8, high: u8, grey: bool) {
-        let mut s = RandState::new(seed);
-        let low = 0xfe.min(low);
-        let high = high.max(low + 1);
-        unsafe {
-            if grey {
-                for px in &mut self.pixels {
-                    let p = (low + s.rand() as u8) % (high - low);
-                    px.rgba = Channel::new(p, p, p, 0xff);
-                }
-            } else {
-                for px in &mut self.pixels {
-                    px.word = s.rand() | !RGB_MASK;
-                    px.rgba = Channel::new(
-                        low + px.rgba.r % (high - low),
-                        low + px.rgba.g % (high - low),
-                        low + px.rgba.b % (high - low),
-                        px.rgba.a,
-                    );
-                }
-            }
-        }
-    }
-
-    fn _flood_fill(b: &mut Buffer, color: Pixel, o: Pixel, x: i32, y: i32) {
-        if y < 0 || y >= b.h || x < 0 || x >= b.w || b.pixels[(x + y * b.w) as usize] != o {
-            return;
-        }
-        /* Fill left */
-        let mut il = x;
-        while il >= 0 && b.pixels[(il + y * b.w) as usize] == o {
-            b.pixels[(il + y * b.w) as usize] = color;
-            il -= 1;
-        }
-        /* Fill right */
-        let mut ir = if x < b.w - 1 { x + 1 } else { x };
-        while ir < b.w && b.pixels[(ir + y * b.w) as usize] == o {
-            b.pixels[(ir + y * b.w) as usize] = color;
-            ir += 1;
-        }
-        /* Fill up and down */
-        while il <= ir {
-            Buffer::_flood_fill(b, color, o, il, y - 1);
-            Buffer::_flood_fill(b, color, o, il, y + 1);
-            il += 1;
-        }
+    /* Overwrites the pixels matching the one at (`x`, `y`) and connected to it,
+     * diagonals included, with `c`, within the clip rect */
+    pub fn flood_fill(&mut self, c: Pixel, x: i32, y: i32) {
+        fill::flood(self, c, x, y, &FloodFill::new(0, DEFAULT_CONNECTIVITY));
     }
 
-    pub fn flood_fill(&mut self, c: Pixel, x: i32, y: i32) {
-        let px = self.get_pixel(x, y);
-        Buffer::_flood_fill(self, c, px, x, y);
+    /* Fills the region around (`x`, `y`) as configured by `opts` */
+    pub fn flood_fill_with(&mut self, c: Pixel, x: i32, y: i32, opts: &FloodFill) {
+        fill::flood(self, c, x, y, opts);
     }
 
     pub fn draw_pixel(&mut self, c: Pixel, x: i32, y: i32) {
         if x >= self.clip.x && x < self.clip.x + self.clip.w && y >= self.clip.y
             && y < self.clip.y + self.clip.h
         {
-            blend_pixel(&self.mode, &mut self.pixels[(x + y * self.w) as usize], c);
+            let mode = self.mode;
+            self.blend_at(&mode, c, x, y);
         }
     }
 
     pub fn draw_line(&mut self, c: Pixel, mut x0: i32, mut y0: i32, mut x1: i32, mut y1: i32) {
+        if self.mode.antialias {
+            return aa::line(self, c, x0 as f32, y0 as f32, x1 as f32, y1 as f32);
+        }
         let steep = (y1 - y0).abs() > (x1 - x0).abs();
         if steep {
             mem::swap(&mut x0, &mut y0);
@@ -700,28 +1461,142 @@ impl Buffer {
         }
     }
 
-    pub fn draw_rect(&mut self, c: Pixel, x: i32, y: i32, w: i32, h: i32) {
+    pub fn draw_thick_line<'a, P: Into<Paint<'a>>>(
+        &mut self,
+        c: P,
+        x0: i32,
+        y0: i32,
+        x1: i32,
+        y1: i32,
+        style: LineStyle,
+    ) {
+        self.draw_polyline(c, &[Point::new(x0, y0), Point::new(x1, y1)], style);
+    }
+
+    /* Draws connected lines through `points`; every pixel is blended once,
+     * however the segments, caps and joins overlap */
+    pub fn draw_polyline<'a, P: Into<Paint<'a>>>(
+        &mut self,
+        c: P,
+        points: &[Point],
+        style: LineStyle,
+    ) {
+        let c = c.into();
+        let pts: Vec<_> = points.iter().map(|p| (p.x as f32, p.y as f32)).collect();
+        raster::fill(self, &c, &raster::stroke(&pts, false, &style), FillRule::NONZERO);
+    }
+
+    /* Draws the outline of the polygon through `points`, closing it back to
+     * the first point */
+    pub fn draw_polygon(&mut self, c: Pixel, points: &[Point]) {
+        let mut pts = points.to_vec();
+        pts.dedup();
+        if pts.len() > 1 && pts[0] == pts[pts.len() - 1] {
+            pts.pop();
+        }
+        match pts.len() {
+            0 => {}
+            1 => self.draw_pixel(c, pts[0].x, pts[0].y),
+            2 => self.draw_line(c, pts[0].x, pts[0].y, pts[1].x, pts[1].y),
+            n => {
+                for i in 0..n {
+                    let (p, q) = (pts[i], pts[(i + 1) % n]);
+                    if self.mode.antialias {
+                        aa::line(self, c, p.x as f32, p.y as f32, q.x as f32, q.y as f32);
+                    } else {
+                        raster::line_open(self, c, p.x, p.y, q.x, q.y);
+                    }
+                }
+            }
+        }
+    }
+
+    /* Fills the polygon through `points`; a pixel is filled if its center lies
+     * inside according to `rule` */
+    pub fn fill_polygon<'a, P: Into<Paint<'a>>>(&mut self, c: P, points: &[Point], rule: FillRule) {
+        let c = c.into();
+        let contour = points.iter().map(|p| (p.x as f32, p.y as f32)).collect();
+        raster::fill(self, &c, &[contour], rule);
+    }
+
+    pub fn draw_rect<'a, P: Into<Paint<'a>>>(&mut self, c: P, x: i32, y: i32, w: i32, h: i32) {
         let mut rect = Rect::new(x, y, w, h);
         clip_rect(&mut rect, &self.clip);
-        for y in (0..rect.h).rev() {
-            for x in (0..rect.w).rev() {
-                blend_pixel(
-                    &self.mode,
-                    &mut self.pixels[(rect.x + (rect.y + y) * self.w + x) as usize],
-                    c,
-                );
-            }
+        let c = c.into();
+        for y in 0..rect.h {
+            paint::span(self, &c, rect.y + y, rect.x, rect.x + rect.w);
         }
     }
 
-    pub fn draw_box(&mut self, c: Pixel, x: i32, y: i32, w: i32, h: i32) {
+    pub fn draw_box<'a, P: Into<Paint<'a>>>(&mut self, c: P, x: i32, y: i32, w: i32, h: i32) {
+        let c = c.into();
         self.draw_rect(c, x + 1, y, w - 1, 1);
         self.draw_rect(c, x, y + h - 1, w - 1, 1);
         self.draw_rect(c, x, y, 1, h - 1);
         self.draw_rect(c, x + w - 1, y + 1, 1, h - 1);
     }
 
-    pub fn draw_circle(&mut self, c: Pixel, x: i32, y: i32, radius: i32) {
+    pub fn draw_line_aa(&mut self, c: Pixel, x0: f32, y0: f32, x1: f32, y1: f32) {
+        aa::line(self, c, x0, y0, x1, y1);
+    }
+
+    /* Draws the outline of a rectangle with its corners rounded by `radii`,
+     * clockwise from the top-left. Radii are limited to half the smaller side */
+    pub fn draw_rounded_box<'a, P: Into<Paint<'a>>>(
+        &mut self,
+        c: P,
+        x: i32,
+        y: i32,
+        w: i32,
+        h: i32,
+        radii: [i32; 4],
+    ) {
+        let c = c.into();
+        if w <= 0 || h <= 0 {
+            return;
+        }
+        let rows = raster::rounded_rows(w, h, radii);
+        for (i, &(l, r)) in rows.iter().enumerate() {
+            /* Pixels not covered by the row above or below are on the edge */
+            let (above, below) = (i.checked_sub(1).map(|i| rows[i]), rows.get(i + 1));
+            let (l2, r2) = match (above, below) {
+                (Some(a), Some(b)) => (a.0.max(b.0), a.1.min(b.1)),
+                _ => (r + 1, l - 1),
+            };
+            let left_end = (l2 - 1).max(l);
+            let right_start = (r2 + 1).min(r);
+            if left_end + 1 >= right_start {
+                self.draw_rect(c, x + l, y + i as i32, r - l + 1, 1);
+            } else {
+                self.draw_rect(c, x + l, y + i as i32, left_end - l + 1, 1);
+                self.draw_rect(c, x + right_start, y + i as i32, r - right_start + 1, 1);
+            }
+        }
+    }
+
+    pub fn fill_rounded_rect<'a, P: Into<Paint<'a>>>(
+        &mut self,
+        c: P,
+        x: i32,
+        y: i32,
+        w: i32,
+        h: i32,
+        radii: [i32; 4],
+    ) {
+        let c = c.into();
+        if w <= 0 || h <= 0 {
+            return;
+        }
+        for (i, (l, r)) in raster::rounded_rows(w, h, radii).into_iter().enumerate() {
+            self.draw_rect(c, x + l, y + i as i32, r - l + 1, 1);
+        }
+    }
+
+    pub fn draw_circle<'a, P: Into<Paint<'a>>>(&mut self, c: P, x: i32, y: i32, radius: i32) {
+        let c = c.into();
+        if self.mode.antialias {
+            return aa::circle(self, &c, x as f32, y as f32, radius as f32);
+        }
         let mut dx = radius.abs();
         let mut dy = 0;
         let mut radius_error = 1 - dx;
@@ -759,7 +1634,15 @@ impl Buffer {
         }
     }
 
+    pub fn draw_circle_aa<'a, P: Into<Paint<'a>>>(&mut self, c: P, x: f32, y: f32, radius: f32) {
+        let c = c.into();
+        aa::circle(self, &c, x, y, radius);
+    }
+
     pub fn draw_ring(&mut self, c: Pixel, x: i32, y: i32, radius: i32) {
+        if self.mode.antialias {
+            return aa::ring(self, c, x as f32, y as f32, radius as f32);
+        }
         /* TODO : Prevent against overdraw? */
         let mut dx = radius.abs();
         let mut dy = 0;
@@ -790,35 +1673,162 @@ impl Buffer {
         }
     }
 
+    pub fn draw_ring_aa(&mut self, c: Pixel, x: f32, y: f32, radius: f32) {
+        aa::ring(self, c, x, y, radius);
+    }
+
+    /* Draws the outline of an ellipse with radii `rx` and `ry` */
+    pub fn draw_ellipse(&mut self, c: Pixel, x: i32, y: i32, rx: i32, ry: i32) {
+        for (dx, dy) in raster::ellipse_outline(rx.abs(), ry.abs()) {
+            self.draw_pixel(c, x + dx, y + dy);
+        }
+    }
+
+    pub fn fill_ellipse<'a, P: Into<Paint<'a>>>(&mut self, c: P, x: i32, y: i32, rx: i32, ry: i32) {
+        let c = c.into();
+        let (rx, ry) = (rx.abs(), ry.abs());
+        /* Clipped completely off-screen? */
+        if x + rx < self.clip.x || x - rx >= self.clip.x + self.clip.w || y + ry < self.clip.y
+            || y - ry >= self.clip.y + self.clip.h
+        {
+            return;
+        }
+        /* Each row is drawn once at its widest */
+        for (dy, &dx) in raster::ellipse_rows(rx, ry).iter().enumerate() {
+            let dy = dy as i32;
+            self.draw_rect(c, x - dx, y + dy, (dx << 1) + 1, 1);
+            if dy != 0 {
+                self.draw_rect(c, x - dx, y - dy, (dx << 1) + 1, 1);
+            }
+        }
+    }
+
+    /* Draws the part of the outline of a circle from angle `start` to `end`,
+     * in radians clockwise from the positive x axis */
+    pub fn draw_arc(&mut self, c: Pixel, x: i32, y: i32, radius: i32, start: f32, end: f32) {
+        let sweep = raster::sweep(start, end);
+        if sweep <= 0f32 {
+            return;
+        }
+        let r = radius.abs();
+        for (dx, dy) in raster::ellipse_outline(r, r) {
+            if raster::in_sector(dx, dy, start, sweep) {
+                self.draw_pixel(c, x + dx, y + dy);
+            }
+        }
+    }
+
+    /* Fills the slice of a circle from angle `start` to `end`, in radians
+     * clockwise from the positive x axis */
+    pub fn fill_pie<'a, P: Into<Paint<'a>>>(
+        &mut self,
+        c: P,
+        x: i32,
+        y: i32,
+        radius: i32,
+        start: f32,
+        end: f32,
+    ) {
+        let c = c.into();
+        let sweep = raster::sweep(start, end);
+        let r = radius.abs();
+        if sweep <= 0f32 || x + r < self.clip.x || x - r >= self.clip.x + self.clip.w
+            || y + r < self.clip.y || y - r >= self.clip.y + self.clip.h
+        {
+            return;
+        }
+        for (dy, &dx) in raster::ellipse_rows(r, r).iter().enumerate() {
+            let (dy, rows) = (dy as i32, if dy == 0 { 1 } else { 2 });
+            for &dy in [dy, -dy].iter().take(rows) {
+                /* Draw each run of the row that lies within the slice */
+                let mut run = None;
+                for i in -dx..(dx + 2) {
+                    match (run, i <= dx && raster::in_sector(i, dy, start, sweep)) {
+                        (None, true) => run = Some(i),
+                        (Some(s), false) => {
+                            self.draw_rect(c, x + s, y + dy, i - s, 1);
+                            run = None;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    /* Draws a quadratic Bezier curve from `p[0]` to `p[2]`, using `p[1]` as
+     * its control point */
+    pub fn draw_bezier_quad(&mut self, c: Pixel, p: &[Point; 3]) {
+        let p: Vec<_> = p.iter().map(|p| (p.x as f32, p.y as f32)).collect();
+        let mut pts = vec![p[0]];
+        path::quad(&mut pts, p[0], p[1], p[2]);
+        raster::outline(self, c, &pts, false);
+    }
+
+    /* Draws a cubic Bezier curve from `p[0]` to `p[3]`, using `p[1]` and
+     * `p[2]` as its control points */
+    pub fn draw_bezier_cubic(&mut self, c: Pixel, p: &[Point; 4]) {
+        let p: Vec<_> = p.iter().map(|p| (p.x as f32, p.y as f32)).collect();
+        let mut pts = vec![p[0]];
+        path::cubic(&mut pts, p[0], p[1], p[2], p[3], 0);
+        raster::outline(self, c, &pts, false);
+    }
+
+    /* Draws the outline of `path` one pixel wide */
+    pub fn draw_path(&mut self, c: Pixel, path: &Path) {
+        for line in path::flatten(path) {
+            raster::outline(self, c, &line.points, line.closed);
+        }
+    }
+
+    pub fn stroke_path<'a, P: Into<Paint<'a>>>(&mut self, c: P, path: &Path, style: LineStyle) {
+        let c = c.into();
+        let mut contours = Vec::new();
+        for line in path::flatten(path) {
+            contours.extend(raster::stroke(&line.points, line.closed, &style));
+        }
+        raster::fill(self, &c, &contours, FillRule::NONZERO);
+    }
+
+    /* Fills `path` with every subpath treated as closed */
+    pub fn fill_path<'a, P: Into<Paint<'a>>>(&mut self, c: P, path: &Path, rule: FillRule) {
+        let c = c.into();
+        let contours: Vec<_> = path::flatten(path).into_iter().map(|l| l.points).collect();
+        raster::fill(self, &c, &contours, rule);
+    }
+
     pub fn draw_text<'a>(&mut self, c: Pixel, font: Font<'a>, txt: &str, x: i32, y: i32) {
         let txt = font.render(txt);
-        let oldc = self.mode.color;
+        self.save_state();
         self.set_color(c);
         self.draw(&txt, x, y, None, None);
-        self.set_color(oldc);
+        self.restore_state();
     }
 
-    pub fn draw(
+    pub fn draw<T: Pixels>(
         &mut self,
-        src: &Buffer,
+        src: &Buffer<T>,
+        x: i32,
+        y: i32,
+        sub: Option<Rect>,
+        t: Option<Transform>,
+    ) {
+        self.try_draw(src, x, y, sub, t)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_draw<T: Pixels>(
+        &mut self,
+        src: &Buffer<T>,
         mut x: i32,
         mut y: i32,
         sub: Option<Rect>,
         t: Option<Transform>,
-    ) {
+    ) -> Result<(), Error> {
         /* Init sub rect */
-        let s = match sub {
-            Some(_s) => {
-                if _s.w <= 0 || _s.h <= 0 {
-                    return;
-                } else if !(_s.x >= 0 && _s.y >= 0 && _s.x + _s.w <= src.w && _s.y + _s.h <= src.h)
-                {
-                    panic!("sub rectangle out of bounds");
-                } else {
-                    _s
-                }
-            }
-            None => Rect::new(0, 0, src.w, src.h),
+        let s = match src.check_sub_rect(sub)? {
+            Some(s) => s,
+            None => return Ok(()),
         };
         /* Draw */
         match t {
@@ -840,86 +1850,283 @@ impl Buffer {
                 }
             }
         }
+        Ok(())
+    }
+
+    /* Draws `src`, or its `sub` rect, with `m` mapping points relative to the
+     * top left of the rect onto the buffer */
+    pub fn draw_affine<T: Pixels>(&mut self, src: &Buffer<T>, sub: Option<Rect>, m: Affine2) {
+        self.try_draw_affine(src, sub, m)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_draw_affine<T: Pixels>(
+        &mut self,
+        src: &Buffer<T>,
+        sub: Option<Rect>,
+        m: Affine2,
+    ) -> Result<(), Error> {
+        let s = match src.check_sub_rect(sub)? {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+        /* Nothing to see if the source collapses to a line */
+        if let Some(inv) = m.invert() {
+            draw::affine(self, src, s, m, inv);
+        }
+        Ok(())
+    }
+
+    /* Draws `src`, or its `sub` rect, stretched so that its top left, top
+     * right, bottom right and bottom left corners land on `quad`, with
+     * perspective. The quad must be convex */
+    pub fn draw_quad<T: Pixels>(&mut self, src: &Buffer<T>, sub: Option<Rect>, quad: [Point; 4]) {
+        self.try_draw_quad(src, sub, quad)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_draw_quad<T: Pixels>(
+        &mut self,
+        src: &Buffer<T>,
+        sub: Option<Rect>,
+        quad: [Point; 4],
+    ) -> Result<(), Error> {
+        if let Some(s) = src.check_sub_rect(sub)? {
+            draw::quad(self, src, s, quad);
+        }
+        Ok(())
+    }
+
+    /* Draws triangles textured with `src`, taking three `indices` into
+     * `vertices` for each one. A trailing index or two that don't make up a
+     * triangle are ignored */
+    pub fn draw_triangles<T: Pixels>(
+        &mut self,
+        src: &Buffer<T>,
+        vertices: &[Vertex],
+        indices: &[u32],
+        mapping: TextureMapping,
+    ) {
+        self.try_draw_triangles(src, vertices, indices, mapping)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_draw_triangles<T: Pixels>(
+        &mut self,
+        src: &Buffer<T>,
+        vertices: &[Vertex],
+        indices: &[u32],
+        mapping: TextureMapping,
+    ) -> Result<(), Error> {
+        if let Some(&i) = indices.iter().find(|&&i| i as usize >= vertices.len()) {
+            return Err(Error::IndexOutOfBounds {
+                index: i as usize,
+                len: vertices.len(),
+            });
+        }
+        for t in indices.chunks(3).filter(|t| t.len() == 3) {
+            let tri = [
+                vertices[t[0] as usize],
+                vertices[t[1] as usize],
+                vertices[t[2] as usize],
+            ];
+            draw::triangle(self, src, tri, mapping);
+        }
+        Ok(())
+    }
+
+    /* Draws `src`, or its `sub` rect, over `dest` with the corners unscaled
+     * and the edges and center stretched or tiled as `slice` says. Corners
+     * shrink if `dest` is too small to fit them */
+    pub fn draw_nine_slice<T: Pixels>(
+        &mut self,
+        src: &Buffer<T>,
+        sub: Option<Rect>,
+        slice: NineSlice,
+        dest: Rect,
+    ) {
+        self.try_draw_nine_slice(src, sub, slice, dest)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_draw_nine_slice<T: Pixels>(
+        &mut self,
+        src: &Buffer<T>,
+        sub: Option<Rect>,
+        slice: NineSlice,
+        dest: Rect,
+    ) -> Result<(), Error> {
+        let s = match src.check_sub_rect(sub)? {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+        if slice.left < 0 || slice.top < 0 || slice.right < 0 || slice.bottom < 0
+            || slice.left + slice.right > s.w || slice.top + slice.bottom > s.h
+        {
+            return Err(Error::InvalidSlice {
+                slice,
+                w: s.w,
+                h: s.h,
+            });
+        }
+        if dest.w > 0 && dest.h > 0 {
+            draw::nine_slice(self, src, s, slice, dest);
+        }
+        Ok(())
+    }
+
+    /* Repeats `src`, or its `sub` rect, across `dest`. `offset` scrolls the
+     * pattern, it is the position within the repeated source shown at the top
+     * left of `dest` */
+    pub fn draw_tiled<T: Pixels>(
+        &mut self,
+        src: &Buffer<T>,
+        sub: Option<Rect>,
+        dest: Rect,
+        offset: Point,
+    ) {
+        self.try_draw_tiled(src, sub, dest, offset)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_draw_tiled<T: Pixels>(
+        &mut self,
+        src: &Buffer<T>,
+        sub: Option<Rect>,
+        dest: Rect,
+        offset: Point,
+    ) -> Result<(), Error> {
+        if let Some(s) = src.check_sub_rect(sub)? {
+            draw::tiled(self, src, s, dest, offset);
+        }
+        Ok(())
+    }
+}
+
+impl Buffer {
+    pub fn noise(&mut self, seed: u32, low: u8, high: u8, grey: bool) {
+        let mut s = RandState::new(seed);
+        let low = 0xfe.min(low);
+        let high = high.max(low + 1);
+        let fmt = self.fmt;
+        unsafe {
+            if grey {
+                for px in &mut self.pixels {
+                    let p = (low + s.rand() as u8) % (high - low);
+                    *px = fmt.store(Pixel::color(p, p, p));
+                }
+            } else {
+                for px in &mut self.pixels {
+                    px.word = s.rand() | !RGB_MASK;
+                    px.rgba = Channel::new(
+                        low + px.rgba.r % (high - low),
+                        low + px.rgba.g % (high - low),
+                        low + px.rgba.b % (high - low),
+                        px.rgba.a,
+                    );
+                    *px = fmt.store(*px);
+                }
+            }
+        }
     }
 
     pub fn desaturate(&mut self, amount: u8) {
+        let fmt = self.fmt;
         unsafe {
             if amount >= 0xfe {
                 /* full amount? don't bother with pixel lerping, just write pixel avg */
-                for p in &mut self.pixels {
+                for px in &mut self.pixels {
+                    let mut p = fmt.load(*px);
                     let avg = ((p.rgba.r as i32 + p.rgba.g as i32 + p.rgba.b as i32) * 341) >> 10;
                     p.rgba.r = avg as u8;
                     p.rgba.g = avg as u8;
                     p.rgba.b = avg as u8;
+                    *px = fmt.store(p);
                 }
             } else {
-                for p in &mut self.pixels {
+                for px in &mut self.pixels {
+                    let mut p = fmt.load(*px);
                     let avg = (((p.rgba.r as i32 + p.rgba.g as i32 + p.rgba.b as i32) * 341) >> 10)
                         as u32;
                     p.rgba.r = lerp!(8, p.rgba.r as u32, avg, amount as u32) as u8;
                     p.rgba.g = lerp!(8, p.rgba.g as u32, avg, amount as u32) as u8;
                     p.rgba.b = lerp!(8, p.rgba.b as u32, avg, amount as u32) as u8;
+                    *px = fmt.store(p);
                 }
             }
         }
     }
 
-    fn check_buffer_size(a: &Buffer, b: &Buffer) {
-        if !(a.w == b.w || a.h == b.h) {
-            panic!("expected buffer sizes to match")
+    fn check_buffer_size(a: &Buffer, b: &Buffer) -> Result<(), Error> {
+        if a.w != b.w || a.h != b.h {
+            return Err(Error::SizeMismatch {
+                expected: (a.w, a.h),
+                found: (b.w, b.h),
+            });
         }
+        Ok(())
     }
 
     pub fn mask(&mut self, mask: &Buffer, channel: Option<ColorChannel>) {
+        self.try_mask(mask, channel)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_mask(&mut self, mask: &Buffer, channel: Option<ColorChannel>) -> Result<(), Error> {
         let channel = channel.unwrap_or(ColorChannel::A);
-        Buffer::check_buffer_size(self, mask);
+        Buffer::check_buffer_size(self, mask)?;
         unsafe {
             for i in (0..(self.w * self.h) as usize).rev() {
+                let mut p = self.fmt.load(self.pixels[i]);
+                let m = mask.fmt.load(mask.pixels[i]);
                 match channel {
                     ColorChannel::R => {
-                        self.pixels[i].rgba.r = ((self.pixels[i].rgba.r as u32
-                            * mask.pixels[i].rgba.r as u32)
-                            >> 8) as u8;
+                        p.rgba.r = ((p.rgba.r as u32 * m.rgba.r as u32) >> 8) as u8;
                     }
                     ColorChannel::G => {
-                        self.pixels[i].rgba.g = ((self.pixels[i].rgba.g as u32
-                            * mask.pixels[i].rgba.g as u32)
-                            >> 8) as u8;
+                        p.rgba.g = ((p.rgba.g as u32 * m.rgba.g as u32) >> 8) as u8;
                     }
                     ColorChannel::B => {
-                        self.pixels[i].rgba.b = ((self.pixels[i].rgba.b as u32
-                            * mask.pixels[i].rgba.b as u32)
-                            >> 8) as u8;
+                        p.rgba.b = ((p.rgba.b as u32 * m.rgba.b as u32) >> 8) as u8;
                     }
                     ColorChannel::A => {
-                        self.pixels[i].rgba.a = ((self.pixels[i].rgba.a as u32
-                            * mask.pixels[i].rgba.a as u32)
-                            >> 8) as u8;
+                        p.rgba.a = ((p.rgba.a as u32 * m.rgba.a as u32) >> 8) as u8;
                     }
                 }
+                self.pixels[i] = self.fmt.store(p);
             }
         }
+        Ok(())
     }
 
     pub fn palette(&mut self, palette: &[Pixel]) {
+        self.try_palette(palette)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_palette(&mut self, palette: &[Pixel]) -> Result<(), Error> {
         let mut pal: [Pixel; 256] = [Pixel::color(0, 0, 0); 256];
         let ncolors = palette.len();
         if ncolors == 0 {
-            panic!("expected non-empty palette")
+            return Err(Error::EmptyPalette);
         }
         unsafe {
             /* load palette from table */
             for i in 0..256 {
-                pal[i].word = palette[(((i * ncolors) >> 8) + 1) as usize].word;
+                pal[i].word = palette[(i * ncolors) >> 8].word;
             }
             /* convert each pixel to palette color based on its brightest channel */
-            for p in &mut self.pixels {
+            let fmt = self.fmt;
+            for px in &mut self.pixels {
+                let mut p = fmt.load(*px);
                 let idx = p.rgba.r.max(p.rgba.b).max(p.rgba.g) as usize;
                 p.rgba.r = pal[idx].rgba.r;
                 p.rgba.g = pal[idx].rgba.g;
                 p.rgba.b = pal[idx].rgba.b;
+                *px = fmt.store(p);
             }
         }
+        Ok(())
     }
 
     fn xorshift64star(x: &mut u64) -> u64 {
@@ -931,11 +2138,12 @@ impl Buffer {
 
     pub fn dissolve(&mut self, amount: u8, seed: u32) {
         let mut seed = (1 << 32) | seed as u64;
-        unsafe {
-            for p in &mut self.pixels {
-                if amount as u64 > (Buffer::xorshift64star(&mut seed) & 0xff) {
-                    p.rgba.a = 0
-                }
+        let fmt = self.fmt;
+        for px in &mut self.pixels {
+            if amount as u64 > (Buffer::xorshift64star(&mut seed) & 0xff) {
+                let mut p = fmt.load(*px);
+                p.rgba.a = 0;
+                *px = fmt.store(p);
             }
         }
     }
@@ -961,7 +2169,7 @@ impl Buffer {
                 let oy = (fxsin(offset_y + ((x * scale_y) >> FX_BITS_10)) * amount_y) as u32
                     >> FX_BITS_10;
                 self.pixels[(y * self.w + x) as usize] =
-                    src.get_pixel(x + ox as i32, y + oy as i32);
+                    self.fmt.store(src.get_pixel(x + ox as i32, y + oy as i32));
             }
         }
     }
@@ -986,28 +2194,46 @@ impl Buffer {
         scale_x: i32,
         scale_y: i32,
     ) {
+        self.try_displace(src, map, channel_x, channel_y, scale_x, scale_y)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_displace(
+        &mut self,
+        src: &Buffer,
+        map: &Buffer,
+        channel_x: ColorChannel,
+        channel_y: ColorChannel,
+        scale_x: i32,
+        scale_y: i32,
+    ) -> Result<(), Error> {
         let scale_x = scale_x << 7;
         let scale_y = scale_y << 7;
-        Buffer::check_buffer_size(self, src);
-        Buffer::check_buffer_size(self, map);
+        Buffer::check_buffer_size(self, src)?;
+        Buffer::check_buffer_size(self, map)?;
         for y in 0..self.h {
             for x in 0..self.w {
-                let cx = ((Buffer::get_channel(map.pixels[(y * map.w + x) as usize], channel_x)
-                    as i32 - (1 << 7)) * scale_x) >> 14;
-                let cy = ((Buffer::get_channel(map.pixels[(y * map.w + x) as usize], channel_y)
-                    as i32 - (1 << 7)) * scale_y) >> 14;
+                let m = map.fmt.load(map.pixels[(y * map.w + x) as usize]);
+                let cx = ((Buffer::get_channel(m, channel_x) as i32 - (1 << 7)) * scale_x) >> 14;
+                let cy = ((Buffer::get_channel(m, channel_y) as i32 - (1 << 7)) * scale_y) >> 14;
                 self.pixels[(y * self.w + x) as usize] =
-                    src.get_pixel(x + cx as i32, y + cy as i32);
+                    self.fmt.store(src.get_pixel(x + cx as i32, y + cy as i32));
             }
         }
+        Ok(())
     }
 
     pub fn blur(&mut self, src: &Buffer, radius_x: i32, radius_y: i32) {
+        self.try_blur(src, radius_x, radius_y)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_blur(&mut self, src: &Buffer, radius_x: i32, radius_y: i32) -> Result<(), Error> {
         let (w, h) = src.get_size();
         let dx = (256 / (radius_x * 2 + 1)) as u32;
         let dy = (256 / (radius_y * 2 + 1)) as u32;
         let bounds = Rect::new(radius_x, radius_y, w - radius_x, h - radius_y);
-        Buffer::check_buffer_size(self, src);
+        Buffer::check_buffer_size(self, src)?;
         let (mut r, mut g, mut b): (u32, u32, u32);
         let mut p2: Pixel;
         /* do blur */
@@ -1018,7 +2244,7 @@ impl Buffer {
                 let in_bounds = in_bounds_y && x >= bounds.x && x < bounds.w;
                 /* blur pixel */
                 macro_rules! GET_PIXEL_FAST {
-                    ($b:expr, $x:expr, $y:expr) => { $b.pixels[($x + $y * w) as usize] }
+                    ($b:expr, $x:expr, $y:expr) => { $b.fmt.load($b.pixels[($x + $y * w) as usize]) }
                 }
                 unsafe {
                     if in_bounds {
@@ -1054,13 +2280,15 @@ impl Buffer {
                             b += (b2 * dx as u32) >> 8;
                         }
                     }
-                    self.pixels[(y * self.w + x) as usize].rgba.r = ((r * dy as u32) >> 8) as u8;
-                    self.pixels[(y * self.w + x) as usize].rgba.g = ((g * dy as u32) >> 8) as u8;
-                    self.pixels[(y * self.w + x) as usize].rgba.b = ((b * dy as u32) >> 8) as u8;
-                    self.pixels[(y * self.w + x) as usize].rgba.a = 0xff;
+                    self.pixels[(y * self.w + x) as usize] = self.fmt.store(Pixel::color(
+                        ((r * dy as u32) >> 8) as u8,
+                        ((g * dy as u32) >> 8) as u8,
+                        ((b * dy as u32) >> 8) as u8,
+                    ));
                 }
             }
         }
+        Ok(())
     }
 }
 
@@ -1071,21 +2299,76 @@ pub struct Font<'a> {
 }
 
 impl<'a> Font<'a> {
-    pub fn new(data: &'a [u8], ptsize: Option<f32>) -> Font<'a> {
+    /* Checks the sfnt/collection header and table directory ourselves before
+     * handing the data to rusttype, which panics on truncated fonts */
+    fn parse(data: &'a [u8]) -> Result<rusttype::Font<'a>, Error> {
+        let be32 = |i: usize| {
+            tu32!(data[i]) << 24 | tu32!(data[i + 1]) << 16 | tu32!(data[i + 2]) << 8
+                | tu32!(data[i + 3])
+        };
+        if data.len() < 12 {
+            return Err(Error::InvalidFont);
+        }
+        let start = match &data[0..4] {
+            b"ttcf" if be32(8) == 0 => return Err(Error::EmptyFontCollection),
+            b"ttcf" if data.len() >= 16 => be32(12) as usize,
+            b"\x00\x01\x00\x00" | b"true" | b"OTTO" => 0,
+            _ => return Err(Error::InvalidFont),
+        };
+        Font::check_tables(data, start)?;
+        FontCollection::from_bytes(data)
+            .font_at(0)
+            .ok_or(Error::InvalidFont)
+    }
+
+    /* Checks that the font whose table directory is at `start` has every table
+     * rusttype needs, and that all of its tables lie within `data` */
+    fn check_tables(data: &[u8], start: usize) -> Result<(), Error> {
+        const REQUIRED: [&[u8]; 7] =
+            [b"cmap", b"glyf", b"head", b"hhea", b"hmtx", b"loca", b"maxp"];
+        let be32 = |i: usize| {
+            (tu32!(data[i]) << 24 | tu32!(data[i + 1]) << 16 | tu32!(data[i + 2]) << 8
+                | tu32!(data[i + 3])) as usize
+        };
+        let fits = |offset: usize, len: usize| {
+            offset.checked_add(len).is_some_and(|end| end <= data.len())
+        };
+        if !fits(start, 12) {
+            return Err(Error::InvalidFont);
+        }
+        let tables = be32(start + 4) >> 16;
+        if !fits(start + 12, tables * 16) {
+            return Err(Error::InvalidFont);
+        }
+        let mut found = [false; 7];
+        for r in (0..tables).map(|i| start + 12 + i * 16) {
+            if !fits(be32(r + 8), be32(r + 12)) {
+                return Err(Error::InvalidFont);
+            }
+            if let Some(i) = REQUIRED.iter().position(|tag| *tag == &data[r..r + 4]) {
+                found[i] = true;
+            }
+        }
+        if found.iter().all(|&f| f) {
+            Ok(())
+        } else {
+            Err(Error::InvalidFont)
+        }
+    }
+
+    pub fn new(data: &'a [u8], ptsize: Option<f32>) -> Result<Font<'a>, Error> {
         let mut font = Font {
-            font: FontCollection::from_bytes(data).into_font().unwrap(),
+            font: Font::parse(data)?,
             ptsize: 0.0,
             offset: 0.0,
         };
         font.ptsize(ptsize.unwrap_or(DEFAULT_FONT_SIZE));
-        font
+        Ok(font)
     }
 
     pub fn default(ptsize: Option<f32>) -> Font<'a> {
         let mut font = Font {
-            font: FontCollection::from_bytes(DEFAULT_FONT_DATA)
-                .into_font()
-                .unwrap(),
+            font: Font::parse(DEFAULT_FONT_DATA).expect("embedded font is invalid"),
             ptsize: 0.0,
             offset: 0.0,
         };
@@ -0,0 +1,251 @@
+use super::*;
+use std::f32::consts::PI;
+
+/* Number of precomputed colors along a gradient */
+const LUT_SIZE: usize = 256;
+
+/* How a gradient continues past its first and last stop */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Spread {
+    PAD,
+    REPEAT,
+    REFLECT,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Shape {
+    Linear(f32, f32, f32, f32),
+    Radial(f32, f32, f32),
+    Conic(f32, f32, f32),
+}
+
+/* Colors blending between stops, positioned in buffer coordinates */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+    shape: Shape,
+    stops: Vec<(f32, Pixel)>,
+    spread: Spread,
+    lut: Vec<Pixel>,
+}
+
+impl Gradient {
+    fn new(shape: Shape) -> Gradient {
+        Gradient {
+            shape,
+            stops: Vec::new(),
+            spread: Spread::PAD,
+            lut: vec![Pixel::pixel(0, 0, 0, 0); LUT_SIZE],
+        }
+    }
+
+    /* Runs from the first stop at (`x0`, `y0`) to the last at (`x1`, `y1`) */
+    pub fn linear(x0: f32, y0: f32, x1: f32, y1: f32) -> Gradient {
+        Gradient::new(Shape::Linear(x0, y0, x1, y1))
+    }
+
+    /* Runs from the first stop at (`x`, `y`) to the last at `radius` */
+    pub fn radial(x: f32, y: f32, radius: f32) -> Gradient {
+        Gradient::new(Shape::Radial(x, y, radius))
+    }
+
+    /* Runs clockwise around (`x`, `y`), starting at `angle` in radians */
+    pub fn conic(x: f32, y: f32, angle: f32) -> Gradient {
+        Gradient::new(Shape::Conic(x, y, angle))
+    }
+
+    /* Adds a color at `offset`, from 0 to 1, along the gradient. Stops added at
+     * the same offset make a hard edge */
+    pub fn add_stop(&mut self, offset: f32, c: Pixel) -> &mut Gradient {
+        let offset = offset.clamp(0f32, 1f32);
+        let i = self.stops.iter().take_while(|s| s.0 <= offset).count();
+        self.stops.insert(i, (offset, c));
+        self.build_lut();
+        self
+    }
+
+    pub fn set_spread(&mut self, spread: Spread) -> &mut Gradient {
+        self.spread = spread;
+        self
+    }
+
+    /* Returns the color at `t` along the gradient, after applying the
+     * spread */
+    pub fn color_at(&self, t: f32) -> Pixel {
+        let t = match self.spread {
+            Spread::PAD => t.clamp(0f32, 1f32),
+            Spread::REPEAT => t - t.floor(),
+            Spread::REFLECT => {
+                let t = t.rem_euclid(2f32);
+                if t > 1f32 {
+                    2f32 - t
+                } else {
+                    t
+                }
+            }
+        };
+        if t.is_nan() {
+            return self.lut[0];
+        }
+        self.lut[(t * (LUT_SIZE - 1) as f32 + 0.5) as usize]
+    }
+
+    fn build_lut(&mut self) {
+        let stops = &self.stops;
+        for (i, p) in self.lut.iter_mut().enumerate() {
+            let t = i as f32 / (LUT_SIZE - 1) as f32;
+            let next = stops.iter().position(|s| s.0 > t).unwrap_or(stops.len());
+            *p = if next == 0 {
+                stops[0].1
+            } else if next == stops.len() {
+                stops[next - 1].1
+            } else {
+                let (a, b) = (stops[next - 1], stops[next]);
+                lerp(a.1, b.1, (t - a.0) / (b.0 - a.0))
+            };
+        }
+    }
+
+    /* Position along the gradient of the point (`x`, `y`) */
+    fn offset(&self, x: f32, y: f32) -> f32 {
+        match self.shape {
+            Shape::Linear(x0, y0, x1, y1) => {
+                let (dx, dy) = (x1 - x0, y1 - y0);
+                let len2 = dx * dx + dy * dy;
+                if len2 == 0f32 {
+                    0f32
+                } else {
+                    ((x - x0) * dx + (y - y0) * dy) / len2
+                }
+            }
+            Shape::Radial(cx, cy, r) => {
+                let d = ((x - cx) * (x - cx) + (y - cy) * (y - cy)).sqrt();
+                if r == 0f32 {
+                    1f32
+                } else {
+                    d / r.abs()
+                }
+            }
+            Shape::Conic(cx, cy, angle) => {
+                ((y - cy).atan2(x - cx) - angle).rem_euclid(2f32 * PI) / (2f32 * PI)
+            }
+        }
+    }
+}
+
+fn lerp(a: Pixel, b: Pixel, t: f32) -> Pixel {
+    let mix = |a: u8, b: u8| (f32::from(a) + (f32::from(b) - f32::from(a)) * t + 0.5) as u8;
+    unsafe {
+        Pixel::pixel(
+            mix(a.rgba.r, b.rgba.r),
+            mix(a.rgba.g, b.rgba.g),
+            mix(a.rgba.b, b.rgba.b),
+            mix(a.rgba.a, b.rgba.a),
+        )
+    }
+}
+
+/* A source buffer repeated in every direction, positioned in buffer
+ * coordinates */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern<'a> {
+    src: BufferView<'a>,
+    x: i32,
+    y: i32,
+    t: Option<Transform>,
+}
+
+impl<'a> Pattern<'a> {
+    /* Tiles `src` with its top left corner at the origin */
+    pub fn new<S: Pixels>(src: &'a Buffer<S>) -> Pattern<'a> {
+        Pattern {
+            src: src.view(),
+            x: 0,
+            y: 0,
+            t: None,
+        }
+    }
+
+    /* Moves the top left corner of a tile to (`x`, `y`) */
+    pub fn set_offset(&mut self, x: i32, y: i32) -> &mut Pattern<'a> {
+        self.x = x;
+        self.y = y;
+        self
+    }
+
+    /* Rotates and scales the tiles around the offset, treating the
+     * transform's origin as a point on the source like `Buffer::draw` */
+    pub fn set_transform(&mut self, t: Option<Transform>) -> &mut Pattern<'a> {
+        self.t = t;
+        self
+    }
+
+    /* Returns the source pixel shown at (`x`, `y`) */
+    fn color_at(&self, x: i32, y: i32) -> Pixel {
+        let (mut dx, mut dy) = (x - self.x, y - self.y);
+        if let Some(t) = self.t {
+            /* Map back into the source by undoing the rotation and scale */
+            let (fx, fy) = (dx as f32, dy as f32);
+            let (sin, cos) = t.r.sin_cos();
+            let u = (fx * cos + fy * sin) / t.sx + t.ox;
+            let v = (fy * cos - fx * sin) / t.sy + t.oy;
+            if !u.is_finite() || !v.is_finite() {
+                return Pixel { word: 0 };
+            }
+            dx = u.floor() as i32;
+            dy = v.floor() as i32;
+        }
+        let src = &self.src;
+        let (sx, sy) = (dx.rem_euclid(src.w), dy.rem_euclid(src.h));
+        src.fmt.load(src.pixels[(sx + sy * src.pitch) as usize])
+    }
+}
+
+/* What a shape is filled with; any `Pixel` converts into a solid paint */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Paint<'a> {
+    Solid(Pixel),
+    Gradient(&'a Gradient),
+    Pattern(&'a Pattern<'a>),
+}
+
+impl<'a> From<Pixel> for Paint<'a> {
+    fn from(c: Pixel) -> Paint<'a> {
+        Paint::Solid(c)
+    }
+}
+
+impl<'a> From<&'a Gradient> for Paint<'a> {
+    fn from(g: &'a Gradient) -> Paint<'a> {
+        Paint::Gradient(g)
+    }
+}
+
+impl<'a> From<&'a Pattern<'a>> for Paint<'a> {
+    fn from(p: &'a Pattern<'a>) -> Paint<'a> {
+        Paint::Pattern(p)
+    }
+}
+
+/* Returns the color of `paint` at the pixel (`x`, `y`) */
+pub fn sample(paint: &Paint, x: i32, y: i32) -> Pixel {
+    match *paint {
+        Paint::Solid(c) => c,
+        Paint::Gradient(g) => g.color_at(g.offset(x as f32, y as f32)),
+        Paint::Pattern(p) => p.color_at(x, y),
+    }
+}
+
+/* Blends `paint` onto the pixels `x0..x1` of row `y`, within the clip rect.
+ * Every filled shape ends up here */
+pub fn span<S: PixelsMut>(b: &mut Buffer<S>, paint: &Paint, y: i32, x0: i32, x1: i32) {
+    if y < b.clip.y || y >= b.clip.y + b.clip.h {
+        return;
+    }
+    let x0 = x0.max(b.clip.x);
+    let x1 = x1.min(b.clip.x + b.clip.w);
+    let mode = b.mode;
+    for x in x0..x1 {
+        let c = sample(paint, x, y);
+        b.blend_at(&mode, c, x, y);
+    }
+}
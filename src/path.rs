@@ -0,0 +1,156 @@
+/* Curves are split until no control point is further than this many pixels
+ * from the chord */
+const TOLERANCE: f32 = 0.25;
+
+/* Deepest subdivision, bounds the work done for degenerate curves */
+const MAX_DEPTH: u32 = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Segment {
+    Move(f32, f32),
+    Line(f32, f32),
+    Quad(f32, f32, f32, f32),
+    Cubic(f32, f32, f32, f32, f32, f32),
+    Close,
+}
+
+/* An outline of lines and curves made of any number of subpaths, drawn with
+ * `Buffer::draw_path`, `Buffer::stroke_path` or `Buffer::fill_path` */
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Path {
+    segments: Vec<Segment>,
+}
+
+impl Path {
+    pub fn new() -> Path {
+        Path::default()
+    }
+
+    /* Starts a new subpath at (`x`, `y`) */
+    pub fn move_to(&mut self, x: f32, y: f32) -> &mut Path {
+        self.segments.push(Segment::Move(x, y));
+        self
+    }
+
+    pub fn line_to(&mut self, x: f32, y: f32) -> &mut Path {
+        self.segments.push(Segment::Line(x, y));
+        self
+    }
+
+    /* Quadratic curve to (`x`, `y`) with control point (`cx`, `cy`) */
+    pub fn quad_to(&mut self, cx: f32, cy: f32, x: f32, y: f32) -> &mut Path {
+        self.segments.push(Segment::Quad(cx, cy, x, y));
+        self
+    }
+
+    /* Cubic curve to (`x`, `y`) with control points (`c1x`, `c1y`) and
+     * (`c2x`, `c2y`) */
+    pub fn cubic_to(
+        &mut self,
+        c1x: f32,
+        c1y: f32,
+        c2x: f32,
+        c2y: f32,
+        x: f32,
+        y: f32,
+    ) -> &mut Path {
+        self.segments.push(Segment::Cubic(c1x, c1y, c2x, c2y, x, y));
+        self
+    }
+
+    /* Closes the current subpath back to its first point */
+    pub fn close(&mut self) -> &mut Path {
+        self.segments.push(Segment::Close);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+}
+
+/* A flattened subpath, and whether it was closed */
+pub struct Polyline {
+    pub points: Vec<(f32, f32)>,
+    pub closed: bool,
+}
+
+fn dist_to_chord(p: (f32, f32), a: (f32, f32), b: (f32, f32)) -> f32 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let len = (dx * dx + dy * dy).sqrt();
+    if len == 0f32 {
+        ((p.0 - a.0) * (p.0 - a.0) + (p.1 - a.1) * (p.1 - a.1)).sqrt()
+    } else {
+        ((p.0 - a.0) * dy - (p.1 - a.1) * dx).abs() / len
+    }
+}
+
+fn mid(a: (f32, f32), b: (f32, f32)) -> (f32, f32) {
+    ((a.0 + b.0) / 2f32, (a.1 + b.1) / 2f32)
+}
+
+/* Appends the points of the cubic curve after `p0`, splitting it in halves
+ * until it is flat enough to be drawn as a line */
+pub fn cubic(
+    out: &mut Vec<(f32, f32)>,
+    p0: (f32, f32),
+    p1: (f32, f32),
+    p2: (f32, f32),
+    p3: (f32, f32),
+    depth: u32,
+) {
+    let flat = dist_to_chord(p1, p0, p3).max(dist_to_chord(p2, p0, p3));
+    if flat.is_nan() || flat <= TOLERANCE || depth >= MAX_DEPTH {
+        out.push(p3);
+        return;
+    }
+    let (p01, p12, p23) = (mid(p0, p1), mid(p1, p2), mid(p2, p3));
+    let (p012, p123) = (mid(p01, p12), mid(p12, p23));
+    let m = mid(p012, p123);
+    cubic(out, p0, p01, p012, m, depth + 1);
+    cubic(out, m, p123, p23, p3, depth + 1);
+}
+
+/* Appends the points of the quadratic curve after `p0` */
+pub fn quad(out: &mut Vec<(f32, f32)>, p0: (f32, f32), c: (f32, f32), p1: (f32, f32)) {
+    /* Every quadratic curve is also a cubic one */
+    let c1 = (p0.0 + (c.0 - p0.0) * 2f32 / 3f32, p0.1 + (c.1 - p0.1) * 2f32 / 3f32);
+    let c2 = (p1.0 + (c.0 - p1.0) * 2f32 / 3f32, p1.1 + (c.1 - p1.1) * 2f32 / 3f32);
+    cubic(out, p0, c1, c2, p1, 0);
+}
+
+/* Converts the path into polylines; segments drawn before any `move_to`
+ * start at the origin */
+pub fn flatten(path: &Path) -> Vec<Polyline> {
+    let mut out = Vec::new();
+    let mut points = vec![(0f32, 0f32)];
+    let mut start = (0f32, 0f32);
+    for seg in &path.segments {
+        let last = points[points.len() - 1];
+        match *seg {
+            Segment::Move(x, y) => {
+                if points.len() > 1 {
+                    out.push(Polyline { points, closed: false });
+                }
+                start = (x, y);
+                points = vec![start];
+            }
+            Segment::Line(x, y) => points.push((x, y)),
+            Segment::Quad(cx, cy, x, y) => quad(&mut points, last, (cx, cy), (x, y)),
+            Segment::Cubic(c1x, c1y, c2x, c2y, x, y) => {
+                cubic(&mut points, last, (c1x, c1y), (c2x, c2y), (x, y), 0)
+            }
+            Segment::Close => {
+                if points.len() > 1 {
+                    out.push(Polyline { points, closed: true });
+                }
+                /* Whatever follows starts over from the same point */
+                points = vec![start];
+            }
+        }
+    }
+    if points.len() > 1 {
+        out.push(Polyline { points, closed: false });
+    }
+    out
+}
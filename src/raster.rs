@@ -0,0 +1,422 @@
+use super::*;
+use std::cmp::Ordering;
+use std::f32::consts::PI;
+
+/* A closed polygon in buffer coordinates; pixel centers lie on integer
+ * coordinates */
+pub type Contour = Vec<(f32, f32)>;
+
+/* Integer coordinates land exactly on pixel centers, so contours that meet
+ * there can disagree by a rounding error; treat anything closer than this as
+ * on the boundary */
+const EPSILON: f32 = 1e-3;
+
+struct Edge {
+    y0: f32,
+    y1: f32,
+    x: f32,
+    dxdy: f32,
+    dir: i32,
+}
+
+/* Fills `contours` as a single shape, deciding what is inside by `rule`.
+ * Every pixel whose center is inside is blended exactly once */
+pub fn fill<S: PixelsMut>(b: &mut Buffer<S>, c: &Paint, contours: &[Contour], rule: FillRule) {
+    let mut edges = Vec::new();
+    let (mut ymin, mut ymax) = (f32::INFINITY, f32::NEG_INFINITY);
+    for contour in contours {
+        for (i, &p) in contour.iter().enumerate() {
+            let q = contour[(i + 1) % contour.len()];
+            if p.1 == q.1 {
+                continue;
+            }
+            let (top, bot, dir) = if p.1 < q.1 { (p, q, 1) } else { (q, p, -1) };
+            edges.push(Edge {
+                y0: top.1,
+                y1: bot.1,
+                x: top.0,
+                dxdy: (bot.0 - top.0) / (bot.1 - top.1),
+                dir,
+            });
+            ymin = ymin.min(top.1);
+            ymax = ymax.max(bot.1);
+        }
+    }
+    if edges.is_empty() {
+        return;
+    }
+    /* Sample each row at the pixel centers */
+    let y0 = ((ymin - EPSILON).ceil() as i32).max(b.clip.y);
+    let y1 = ((ymax - EPSILON).ceil() as i32).min(b.clip.y + b.clip.h);
+    let mut xs: Vec<(f32, i32)> = Vec::new();
+    for y in y0..y1 {
+        let fy = y as f32;
+        xs.clear();
+        for e in &edges {
+            if fy >= e.y0 - EPSILON && fy < e.y1 - EPSILON {
+                xs.push((e.x + (fy - e.y0) * e.dxdy, e.dir));
+            }
+        }
+        xs.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        let mut wind = 0;
+        let mut start = 0f32;
+        for &(x, dir) in &xs {
+            let was_inside = inside(wind, rule);
+            wind += dir;
+            match (was_inside, inside(wind, rule)) {
+                (false, true) => start = x,
+                (true, false) => span(b, c, y, start, x),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn inside(wind: i32, rule: FillRule) -> bool {
+    match rule {
+        FillRule::NONZERO => wind != 0,
+        FillRule::EVENODD => wind & 1 != 0,
+    }
+}
+
+/* Blends the pixels of row `y` whose centers lie in `xa..xb` */
+fn span<S: PixelsMut>(b: &mut Buffer<S>, c: &Paint, y: i32, xa: f32, xb: f32) {
+    let x0 = (xa - EPSILON).ceil() as i32;
+    let x1 = (xb - EPSILON).ceil() as i32;
+    paint::span(b, c, y, x0, x1);
+}
+
+/* Draws a line from (`x0`, `y0`) up to but not including (`x1`, `y1`), so
+ * that connected lines meet without drawing their shared points twice */
+pub fn line_open<S: PixelsMut>(
+    b: &mut Buffer<S>,
+    c: Pixel,
+    x0: i32,
+    y0: i32,
+    x1: i32,
+    y1: i32,
+) {
+    let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+    let (sx, sy) = (if x0 < x1 { 1 } else { -1 }, if y0 < y1 { 1 } else { -1 });
+    let (mut x, mut y) = (x0, y0);
+    let mut error = dx + dy;
+    while x != x1 || y != y1 {
+        b.draw_pixel(c, x, y);
+        let e2 = 2 * error;
+        if e2 >= dy {
+            error += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            error += dx;
+            y += sy;
+        }
+    }
+}
+
+/* Draws one pixel wide lines through `pts`, each pixel once */
+pub fn outline<S: PixelsMut>(b: &mut Buffer<S>, c: Pixel, pts: &[(f32, f32)], closed: bool) {
+    let n = pts.len();
+    if b.mode.antialias {
+        let segs = if closed && n > 2 { n } else { n.saturating_sub(1) };
+        for i in 0..segs {
+            let (p, q) = (pts[i], pts[(i + 1) % n]);
+            aa::line(b, c, p.0, p.1, q.0, q.1);
+        }
+        return;
+    }
+    let mut p: Vec<(i32, i32)> = pts.iter()
+        .map(|p| (p.0.round() as i32, p.1.round() as i32))
+        .collect();
+    p.dedup();
+    if closed && p.len() > 1 && p[0] == p[p.len() - 1] {
+        p.pop();
+    }
+    let n = p.len();
+    if n == 0 {
+        return;
+    }
+    let closed = closed && n > 2;
+    for i in 0..(if closed { n } else { n - 1 }) {
+        let (p, q) = (p[i], p[(i + 1) % n]);
+        line_open(b, c, p.0, p.1, q.0, q.1);
+    }
+    if !closed {
+        b.draw_pixel(c, p[n - 1].0, p[n - 1].1);
+    }
+}
+
+fn area(c: &[(f32, f32)]) -> f32 {
+    let mut a = 0f32;
+    for (i, p) in c.iter().enumerate() {
+        let q = c[(i + 1) % c.len()];
+        a += p.0 * q.1 - q.0 * p.1;
+    }
+    a / 2f32
+}
+
+/* Adds `c` wound the same way as every other contour so that overlapping
+ * contours add up rather than cancel out */
+fn push_oriented(out: &mut Vec<Contour>, mut c: Contour) {
+    if area(&c) < 0f32 {
+        c.reverse();
+    }
+    out.push(c);
+}
+
+/* Approximates a circle with enough segments to stay within a quarter pixel
+ * of the true edge */
+pub fn circle(x: f32, y: f32, r: f32) -> Contour {
+    let n = if r > 0.25 {
+        (PI / (1f32 - 0.25 / r).acos()).ceil().clamp(8f32, 256f32) as usize
+    } else {
+        8
+    };
+    (0..n)
+        .map(|i| {
+            let a = i as f32 * 2f32 * PI / n as f32;
+            (x + a.cos() * r, y + a.sin() * r)
+        })
+        .collect()
+}
+
+fn unit(a: (f32, f32), b: (f32, f32)) -> (f32, f32) {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let len = (dx * dx + dy * dy).sqrt();
+    (dx / len, dy / len)
+}
+
+/* Returns the contours covering a line of `style` through `pts`, closing it
+ * back to the first point if `closed` */
+pub fn stroke(pts: &[(f32, f32)], closed: bool, style: &LineStyle) -> Vec<Contour> {
+    let hw = style.width / 2f32;
+    let mut out = Vec::new();
+    if hw.is_nan() || hw <= 0f32 {
+        return out;
+    }
+    /* Repeated points have no direction */
+    let mut p: Vec<(f32, f32)> = Vec::with_capacity(pts.len());
+    for &q in pts {
+        if p.last() != Some(&q) {
+            p.push(q);
+        }
+    }
+    if closed && p.len() > 1 && p[0] == p[p.len() - 1] {
+        p.pop();
+    }
+    let n = p.len();
+    if n == 0 {
+        return out;
+    }
+    if n == 1 {
+        let (x, y) = p[0];
+        match style.cap {
+            LineCap::BUTT => {}
+            LineCap::ROUND => push_oriented(&mut out, circle(x, y, hw)),
+            LineCap::SQUARE => push_oriented(
+                &mut out,
+                vec![
+                    (x - hw, y - hw),
+                    (x + hw, y - hw),
+                    (x + hw, y + hw),
+                    (x - hw, y + hw),
+                ],
+            ),
+        }
+        return out;
+    }
+    /* Segments */
+    let nseg = if closed { n } else { n - 1 };
+    for i in 0..nseg {
+        let (mut a, mut b) = (p[i], p[(i + 1) % n]);
+        let d = unit(a, b);
+        if !closed && style.cap == LineCap::SQUARE {
+            if i == 0 {
+                a = (a.0 - d.0 * hw, a.1 - d.1 * hw);
+            }
+            if i == nseg - 1 {
+                b = (b.0 + d.0 * hw, b.1 + d.1 * hw);
+            }
+        }
+        let (nx, ny) = (-d.1 * hw, d.0 * hw);
+        push_oriented(
+            &mut out,
+            vec![
+                (a.0 + nx, a.1 + ny),
+                (b.0 + nx, b.1 + ny),
+                (b.0 - nx, b.1 - ny),
+                (a.0 - nx, a.1 - ny),
+            ],
+        );
+    }
+    /* Caps */
+    if !closed && style.cap == LineCap::ROUND {
+        push_oriented(&mut out, circle(p[0].0, p[0].1, hw));
+        push_oriented(&mut out, circle(p[n - 1].0, p[n - 1].1, hw));
+    }
+    /* Joins */
+    let (first, last) = if closed { (0, n) } else { (1, n - 1) };
+    for i in first..last {
+        join(&mut out, p[(i + n - 1) % n], p[i], p[(i + 1) % n], hw, style);
+    }
+    out
+}
+
+/* Fills the gap on the outside of the turn at `v` */
+fn join(
+    out: &mut Vec<Contour>,
+    prev: (f32, f32),
+    v: (f32, f32),
+    next: (f32, f32),
+    hw: f32,
+    style: &LineStyle,
+) {
+    let (d0, d1) = (unit(prev, v), unit(v, next));
+    let cross = d0.0 * d1.1 - d0.1 * d1.0;
+    let dot = d0.0 * d1.0 + d0.1 * d1.1;
+    if cross.abs() < 1e-6 && dot > 0f32 {
+        /* Straight, nothing to fill */
+        return;
+    }
+    if style.join == LineJoin::ROUND {
+        push_oriented(out, circle(v.0, v.1, hw));
+        return;
+    }
+    /* The outer side is opposite the direction of the turn */
+    let s = if cross > 0f32 { -hw } else { hw };
+    let n0 = (-d0.1 * s, d0.0 * s);
+    let n1 = (-d1.1 * s, d1.0 * s);
+    let a = (v.0 + n0.0, v.1 + n0.1);
+    let b = (v.0 + n1.0, v.1 + n1.1);
+    if style.join == LineJoin::MITER {
+        /* The miter length relative to the line width is 1 / cos(angle / 2) */
+        let cos_half = ((1f32 + dot) / 2f32).max(0f32).sqrt();
+        if cos_half > 0f32 && 1f32 / cos_half <= style.miter_limit {
+            let (mx, my) = (n0.0 + n1.0, n0.1 + n1.1);
+            let len = (mx * mx + my * my).sqrt();
+            let k = hw / cos_half / len;
+            push_oriented(out, vec![v, a, (v.0 + mx * k, v.1 + my * k), b]);
+            return;
+        }
+    }
+    push_oriented(out, vec![v, a, b]);
+}
+
+/* Midpoint ellipse; returns the outline points of the quadrant from (0, `ry`)
+ * to (`rx`, 0), each exactly once */
+pub fn ellipse_quadrant(rx: i32, ry: i32) -> Vec<(i32, i32)> {
+    let mut out = Vec::new();
+    if ry == 0 {
+        out.extend((0..(rx + 1)).map(|px| (px, 0)));
+        return out;
+    }
+    let (a2, b2) = (f64::from(rx) * f64::from(rx), f64::from(ry) * f64::from(ry));
+    let (mut px, mut py) = (0, ry);
+    let (mut dx, mut dy) = (0f64, 2f64 * a2 * f64::from(py));
+    /* Region where the outline is flatter than 45 degrees */
+    let mut d = b2 - a2 * f64::from(ry) + a2 / 4f64;
+    while dx < dy {
+        out.push((px, py));
+        px += 1;
+        dx += 2f64 * b2;
+        if d < 0f64 {
+            d += dx + b2;
+        } else {
+            py -= 1;
+            dy -= 2f64 * a2;
+            d += dx - dy + b2;
+        }
+    }
+    /* Steeper region */
+    let (fx, fy) = (f64::from(px) + 0.5, f64::from(py - 1));
+    let mut d = b2 * fx * fx + a2 * fy * fy - a2 * b2;
+    while py >= 0 {
+        out.push((px, py));
+        py -= 1;
+        dy -= 2f64 * a2;
+        if d > 0f64 {
+            d += a2 - dy;
+        } else {
+            px += 1;
+            dx += 2f64 * b2;
+            d += dx - dy + a2;
+        }
+    }
+    out
+}
+
+/* Returns the half width of each row `0..=ry` of a filled ellipse */
+pub fn ellipse_rows(rx: i32, ry: i32) -> Vec<i32> {
+    let mut rows = vec![0; ry as usize + 1];
+    for (px, py) in ellipse_quadrant(rx, ry) {
+        let w = &mut rows[py as usize];
+        *w = (*w).max(px);
+    }
+    rows
+}
+
+/* Returns the outline points of the whole ellipse, each exactly once */
+pub fn ellipse_outline(rx: i32, ry: i32) -> Vec<(i32, i32)> {
+    let mut out = Vec::new();
+    for (px, py) in ellipse_quadrant(rx, ry) {
+        out.push((px, py));
+        if px != 0 {
+            out.push((-px, py));
+        }
+        if py != 0 {
+            out.push((px, -py));
+            if px != 0 {
+                out.push((-px, -py));
+            }
+        }
+    }
+    out
+}
+
+/* Sweep of the angles from `start` to `end`, clockwise on screen */
+pub fn sweep(start: f32, end: f32) -> f32 {
+    if end - start >= 2f32 * PI {
+        2f32 * PI
+    } else {
+        (end - start).rem_euclid(2f32 * PI)
+    }
+}
+
+/* Whether the offset (`dx`, `dy`) from the center lies within the sector
+ * starting at angle `start` and spanning `sweep` */
+pub fn in_sector(dx: i32, dy: i32, start: f32, sweep: f32) -> bool {
+    if sweep >= 2f32 * PI || (dx == 0 && dy == 0) {
+        return true;
+    }
+    let a = (dy as f32).atan2(dx as f32);
+    (a - start).rem_euclid(2f32 * PI) <= sweep
+}
+
+/* Returns the first and last column of each row of a `w`x`h` rectangle with
+ * its corners rounded by `radii`, clockwise from the top-left */
+pub fn rounded_rows(w: i32, h: i32, radii: [i32; 4]) -> Vec<(i32, i32)> {
+    let max = w.min(h) / 2;
+    let r: Vec<i32> = radii.iter().map(|r| (*r).max(0).min(max)).collect();
+    let corners: Vec<Vec<i32>> = r.iter().map(|&r| ellipse_rows(r, r)).collect();
+    /* Distance from the edge of a row `dy` rows above the lowest row of a
+     * corner */
+    let inset = |i: usize, dy: i32| r[i] - corners[i][dy as usize];
+    (0..h)
+        .map(|y| {
+            let (mut left, mut right) = (0, 0);
+            if y < r[0] {
+                left = inset(0, r[0] - y);
+            }
+            if y < r[1] {
+                right = inset(1, r[1] - y);
+            }
+            if h - 1 - y < r[2] {
+                right = inset(2, r[2] - (h - 1 - y));
+            }
+            if h - 1 - y < r[3] {
+                left = inset(3, r[3] - (h - 1 - y));
+            }
+            (left, w - 1 - right)
+        })
+        .collect()
+}
@@ -66,7 +66,20 @@ pub fn clip_rect_offset(r: &mut Rect, x: &mut i32, y: &mut i32, to: Rect) {
     }
 }
 
-pub fn blend_pixel(m: &DrawMode, d: &mut Pixel, mut s: Pixel) {
+/* Blends `s` onto `d`, a pixel stored in `fmt`. Both `s` and the mode's
+ * color are expected in the native layout */
+#[inline]
+pub fn blend_pixel(m: &DrawMode, fmt: PixelFormat, d: &mut Pixel, s: Pixel) {
+    if fmt == PixelFormat::NATIVE {
+        blend_native(m, d, s);
+    } else {
+        let mut n = fmt.load(*d);
+        blend_native(m, &mut n, s);
+        *d = fmt.store(n);
+    }
+}
+
+fn blend_native(m: &DrawMode, d: &mut Pixel, mut s: Pixel) {
     unsafe {
         let alpha = ((tu32!(s.rgba.a) * tu32!(m.alpha)) >> 8) as u8;
         if alpha <= 1 {
@@ -88,9 +101,9 @@ pub fn blend_pixel(m: &DrawMode, d: &mut Pixel, mut s: Pixel) {
                 s.rgba.b = 0xff.min(tu32!(d.rgba.b) + tu32!(s.rgba.b)) as u8;
             }
             BlendMode::SUBTRACT => {
-                s.rgba.r = 0i32.min(i32::from(d.rgba.r) - i32::from(s.rgba.r)) as u8;
-                s.rgba.g = 0i32.min(i32::from(d.rgba.g) - i32::from(s.rgba.g)) as u8;
-                s.rgba.b = 0i32.min(i32::from(d.rgba.b) - i32::from(s.rgba.b)) as u8;
+                s.rgba.r = 0i32.max(i32::from(d.rgba.r) - i32::from(s.rgba.r)) as u8;
+                s.rgba.g = 0i32.max(i32::from(d.rgba.g) - i32::from(s.rgba.g)) as u8;
+                s.rgba.b = 0i32.max(i32::from(d.rgba.b) - i32::from(s.rgba.b)) as u8;
             }
             BlendMode::MULTIPLY => {
                 s.rgba.r = ((u32::from(s.rgba.r) * tu32!(d.rgba.r)) >> 8) as u8;
@@ -98,14 +111,18 @@ pub fn blend_pixel(m: &DrawMode, d: &mut Pixel, mut s: Pixel) {
                 s.rgba.b = ((u32::from(s.rgba.b) * tu32!(d.rgba.b)) >> 8) as u8;
             }
             BlendMode::LIGHTEN => {
-                s = if s.rgba.r + s.rgba.g + s.rgba.b > d.rgba.r + d.rgba.g + d.rgba.b {
+                s = if tu32!(s.rgba.r) + tu32!(s.rgba.g) + tu32!(s.rgba.b)
+                    > tu32!(d.rgba.r) + tu32!(d.rgba.g) + tu32!(d.rgba.b)
+                {
                     s
                 } else {
                     *d
                 }
             }
             BlendMode::DARKEN => {
-                s = if s.rgba.r + s.rgba.g + s.rgba.b < d.rgba.r + d.rgba.g + d.rgba.b {
+                s = if tu32!(s.rgba.r) + tu32!(s.rgba.g) + tu32!(s.rgba.b)
+                    < tu32!(d.rgba.r) + tu32!(d.rgba.g) + tu32!(d.rgba.b)
+                {
                     s
                 } else {
                     *d
@@ -131,7 +148,7 @@ pub fn blend_pixel(m: &DrawMode, d: &mut Pixel, mut s: Pixel) {
             d.rgba.b = lerp!(8u32, d.rgba.b, s.rgba.b, alpha) as u8;
         } else {
             let a = (0xff - ((tu32!(0xff - d.rgba.a) * tu32!(0xff - alpha)) >> 8)) as u8;
-            let _z = (tu32!(d.rgba.a * (0xff - alpha)) >> 8) as u8;
+            let _z = ((tu32!(d.rgba.a) * tu32!(0xff - alpha)) >> 8) as u8;
             d.rgba.r = DIV8_TABLE[(((tu32!(d.rgba.r) * tu32!(_z)) >> 8)
                                       + ((tu32!(s.rgba.r) * tu32!(alpha)) >> 8))
                                       as usize][a as usize];
@@ -215,3 +232,35 @@ impl RandState {
         self.w
     }
 }
+
+/* Whether `b` starts like an image stb_image has a decoder for. TGA has no
+ * signature, so its header fields are checked the same way stb_image does */
+pub fn is_known_image(b: &[u8]) -> bool {
+    const MAGIC: [&[u8]; 11] = [
+        b"\xff\xd8\xff",
+        b"\x89PNG\r\n\x1a\n",
+        b"BM",
+        b"GIF87a",
+        b"GIF89a",
+        b"8BPS",
+        b"S\x80\xf64",
+        b"P5",
+        b"P6",
+        b"#?RADIANCE\n",
+        b"#?RGBE\n",
+    ];
+    if MAGIC.iter().any(|m| b.starts_with(m)) {
+        return true;
+    }
+    if b.len() < 18 {
+        return false;
+    }
+    let depth = |d: u8| [8, 15, 16, 24, 32].contains(&d);
+    let w = u16::from(b[12]) | u16::from(b[13]) << 8;
+    let h = u16::from(b[14]) | u16::from(b[15]) << 8;
+    w != 0 && h != 0 && match (b[1], b[2]) {
+        (1, 1) | (1, 9) => depth(b[7]),
+        (0, 2) | (0, 3) | (0, 10) | (0, 11) => depth(b[16]),
+        _ => false,
+    }
+}
@@ -0,0 +1,74 @@
+extern crate sera;
+
+use sera::*;
+use std::env;
+use std::io;
+
+const FORMATS: [ImageFormat; 3] = [ImageFormat::PNG, ImageFormat::BMP, ImageFormat::TGA];
+
+/* Flat areas, gradients, translucency and noise, so both the run-length and
+ * the LZ77 paths of the encoders get exercised */
+fn image() -> Buffer {
+    let mut buf = Buffer::new(67, 41);
+    buf.noise(1234, 0, 0xff, false);
+    buf.draw_rect(Pixel::color(0x10, 0x80, 0xf0), 4, 4, 30, 20);
+    for x in 0..buf.w {
+        buf.set_pixel(Pixel::pixel(x as u8 * 3, 0x40, 0xff - x as u8, x as u8 * 2), x, 30);
+    }
+    buf.set_pixel(Pixel::pixel(0, 0, 0, 0), 66, 40);
+    buf
+}
+
+#[test]
+fn roundtrip() {
+    let src = image();
+    for &fmt in &FORMATS {
+        let mut data = Vec::new();
+        src.encode(&mut data, fmt).unwrap();
+        let res = Buffer::bytes(&data).expect("failed to decode encoded image");
+        assert_eq!(res.get_size(), src.get_size(), "{:?}", fmt);
+        assert!(res.pixels == src.pixels, "{:?}: pixels differ", fmt);
+    }
+}
+
+#[test]
+fn roundtrip_formats() {
+    let mut src = image();
+    let mut expected = Vec::new();
+    src.encode(&mut expected, ImageFormat::PNG).unwrap();
+    for &fmt in &[PixelFormat::RGBA, PixelFormat::ARGB, PixelFormat::ABGR] {
+        src.set_format(fmt);
+        let mut data = Vec::new();
+        src.encode(&mut data, ImageFormat::PNG).unwrap();
+        assert_eq!(data, expected, "{:?}", fmt);
+    }
+}
+
+#[test]
+fn save() {
+    let src = image();
+    let dir = env::temp_dir();
+    for &(fmt, ext) in &[
+        (ImageFormat::PNG, "png"),
+        (ImageFormat::BMP, "bmp"),
+        (ImageFormat::TGA, "tga"),
+    ] {
+        let path = dir.join(format!("sera-save-test.{}", ext));
+        src.save(&path, fmt).unwrap();
+        let res = Buffer::file(&path).expect("failed to load saved image");
+        assert!(res.pixels == src.pixels, "{:?}: pixels differ", fmt);
+    }
+}
+
+#[test]
+fn too_large() {
+    /* TGA stores the size in 16 bits, wider images can't be written */
+    let wide = Buffer::new(70000, 1);
+    let mut data = Vec::new();
+    let err = wide.encode(&mut data, ImageFormat::TGA).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert!(data.is_empty());
+    wide.encode(&mut data, ImageFormat::BMP).unwrap();
+    wide.encode(&mut Vec::new(), ImageFormat::PNG).unwrap();
+    assert!(Buffer::new(0xffff, 1).encode(&mut Vec::new(), ImageFormat::TGA).is_ok());
+}
@@ -0,0 +1,213 @@
+extern crate sera;
+
+use sera::*;
+
+#[test]
+fn missing_file() {
+    match Buffer::file("tests/does-not-exist.png") {
+        Err(Error::Io(_)) => {}
+        res => panic!("expected an i/o error, got {:?}", res.map(|b| b.get_size())),
+    }
+}
+
+#[test]
+fn unsupported_image() {
+    match Buffer::bytes(&b"definitely not an image file"[..]) {
+        Err(Error::UnsupportedFormat) => {}
+        res => panic!("expected UnsupportedFormat, got {:?}", res.map(|b| b.get_size())),
+    }
+}
+
+#[test]
+fn truncated_image() {
+    let mut buf = Buffer::new(32, 32);
+    buf.noise(7, 0, 0xff, false);
+    let mut data = Vec::new();
+    buf.encode(&mut data, ImageFormat::PNG).unwrap();
+    data.truncate(data.len() / 2);
+    match Buffer::bytes(&data) {
+        Err(Error::Decode(ref e)) => assert!(!e.is_empty()),
+        res => panic!("expected a decode error, got {:?}", res.map(|b| b.get_size())),
+    }
+}
+
+#[test]
+fn invalid_font() {
+    let data = b"this is not a truetype font at all";
+    match Font::new(data, None) {
+        Err(Error::InvalidFont) => {}
+        res => panic!("expected InvalidFont, got {:?}", res.err()),
+    }
+    /* Valid sfnt tag, but a table directory that runs past the end */
+    let data = [0, 1, 0, 0, 0, 20, 0, 0, 0, 0, 0, 0, 0, 0];
+    match Font::new(&data, None) {
+        Err(Error::InvalidFont) => {}
+        res => panic!("expected InvalidFont, got {:?}", res.err()),
+    }
+}
+
+#[test]
+fn truncated_font() {
+    let font = include_bytes!("../src/embed/default.ttf");
+    for &len in &[1000, 5000, font.len() - 1] {
+        match Font::new(&font[..len], None) {
+            Err(Error::InvalidFont) => {}
+            res => panic!("{} bytes: expected InvalidFont, got {:?}", len, res.err()),
+        }
+    }
+    /* Every table is there, but one rusttype needs is missing */
+    let mut data = font.to_vec();
+    let tables = usize::from(font[4]) << 8 | usize::from(font[5]);
+    let dir = &mut data[12..12 + 16 * tables];
+    let glyf = dir.chunks(16).position(|r| &r[..4] == b"glyf").unwrap();
+    dir[glyf * 16 + 3] = b'x';
+    match Font::new(&data, None) {
+        Err(Error::InvalidFont) => {}
+        res => panic!("expected InvalidFont, got {:?}", res.err()),
+    }
+}
+
+#[test]
+fn empty_font_collection() {
+    let data = [b't', b't', b'c', b'f', 0, 1, 0, 0, 0, 0, 0, 0];
+    match Font::new(&data, None) {
+        Err(Error::EmptyFontCollection) => {}
+        res => panic!("expected EmptyFontCollection, got {:?}", res.err()),
+    }
+}
+
+#[test]
+fn display() {
+    let e = Font::new(b"nope", None).err().unwrap();
+    assert_eq!(e.to_string(), "invalid font data");
+}
+
+#[test]
+fn invalid_size() {
+    match Buffer::try_new(0, 12) {
+        Err(Error::InvalidSize { w: 0, h: 12 }) => {}
+        res => panic!("expected InvalidSize, got {:?}", res.map(|b| b.get_size())),
+    }
+    assert!(Buffer::try_new(-3, -3).is_err());
+    assert!(Buffer::try_new(1, 1).is_ok());
+}
+
+#[test]
+fn sub_rect_out_of_bounds() {
+    let src = Buffer::new(8, 8);
+    let mut dst = Buffer::new(16, 16);
+    let sub = Rect::new(4, 4, 8, 2);
+    match dst.try_draw(&src, 0, 0, Some(sub), None) {
+        Err(Error::SubRectOutOfBounds { sub: s, w: 8, h: 8 }) => assert_eq!(s, sub),
+        res => panic!("expected SubRectOutOfBounds, got {:?}", res),
+    }
+    assert!(dst.try_copy_pixels(&src, 0, 0, Some(sub), 1.0, 1.0).is_err());
+    /* Empty sub rects draw nothing but are not an error */
+    assert!(dst.try_draw(&src, 0, 0, Some(Rect::new(40, 40, 0, 0)), None).is_ok());
+    assert!(dst.try_draw(&src, 0, 0, Some(Rect::new(0, 0, 8, 8)), None).is_ok());
+}
+
+#[test]
+fn size_mismatch() {
+    let mut dst = Buffer::new(16, 16);
+    let other = Buffer::new(8, 8);
+    let same = Buffer::new(16, 16);
+    match dst.try_mask(&other, None) {
+        Err(Error::SizeMismatch { expected: (16, 16), found: (8, 8) }) => {}
+        res => panic!("expected SizeMismatch, got {:?}", res),
+    }
+    /* A single matching dimension is still a mismatch */
+    let wide = Buffer::new(16, 8);
+    match dst.try_mask(&wide, None) {
+        Err(Error::SizeMismatch { expected: (16, 16), found: (16, 8) }) => {}
+        res => panic!("expected SizeMismatch, got {:?}", res),
+    }
+    assert!(dst.try_blur(&other, 1, 1).is_err());
+    assert!(dst.try_push_clip_mask(&other, None).is_err());
+    assert!(dst.try_displace(&same, &other, ColorChannel::R, ColorChannel::G, 1, 1).is_err());
+    assert!(dst.try_displace(&same, &same, ColorChannel::R, ColorChannel::G, 1, 1).is_ok());
+}
+
+#[test]
+fn index_out_of_bounds() {
+    let src = Buffer::new(4, 4);
+    let mut dst = Buffer::new(16, 16);
+    let white = Pixel::color(0xff, 0xff, 0xff);
+    let v = [Vertex::new(0.0, 0.0, 0.0, 0.0, white); 3];
+    match dst.try_draw_triangles(&src, &v, &[0, 1, 3], TextureMapping::AFFINE) {
+        Err(Error::IndexOutOfBounds { index: 3, len: 3 }) => {}
+        res => panic!("expected IndexOutOfBounds, got {:?}", res),
+    }
+    assert!(dst.try_draw_triangles(&src, &v, &[0, 1, 2, 2], TextureMapping::AFFINE).is_ok());
+}
+
+#[test]
+fn invalid_slice() {
+    let src = Buffer::new(8, 8);
+    let mut dst = Buffer::new(16, 16);
+    let dest = Rect::new(0, 0, 16, 16);
+    let slice = NineSlice::new(3, 2, 6, 2);
+    match dst.try_draw_nine_slice(&src, None, slice, dest) {
+        Err(Error::InvalidSlice { slice: s, w: 8, h: 8 }) => assert_eq!(s, slice),
+        res => panic!("expected InvalidSlice, got {:?}", res),
+    }
+    let sub = Some(Rect::new(0, 0, 8, 3));
+    assert!(dst.try_draw_nine_slice(&src, sub, NineSlice::new(2, 2, 2, 2), dest).is_err());
+    assert!(dst.try_draw_nine_slice(&src, None, NineSlice::new(-1, 0, 0, 0), dest).is_err());
+    assert!(dst.try_draw_nine_slice(&src, None, NineSlice::new(4, 4, 4, 4), dest).is_ok());
+}
+
+#[test]
+fn empty_palette() {
+    let mut buf = Buffer::new(4, 4);
+    match buf.try_palette(&[]) {
+        Err(Error::EmptyPalette) => {}
+        res => panic!("expected EmptyPalette, got {:?}", res),
+    }
+}
+
+#[test]
+fn palette_index() {
+    /* Brightness is split evenly between the palette entries, the darkest
+     * pixels taking the first one */
+    let pal = [Pixel::color(0xff, 0, 0), Pixel::color(0, 0xff, 0)];
+    let mut buf = Buffer::new(3, 1);
+    buf.set_pixel(Pixel::color(0, 0, 0), 0, 0);
+    buf.set_pixel(Pixel::color(0x7f, 0, 0), 1, 0);
+    buf.set_pixel(Pixel::color(0xff, 0xff, 0xff), 2, 0);
+    buf.try_palette(&pal).unwrap();
+    let rgb = |p: Pixel| unsafe { (p.rgba.r, p.rgba.g, p.rgba.b) };
+    assert_eq!(rgb(buf.get_pixel(0, 0)), (0xff, 0, 0));
+    assert_eq!(rgb(buf.get_pixel(1, 0)), (0xff, 0, 0));
+    assert_eq!(rgb(buf.get_pixel(2, 0)), (0, 0xff, 0));
+    let mut one = Buffer::new(2, 1);
+    one.set_pixel(Pixel::color(0xff, 0xff, 0xff), 1, 0);
+    one.try_palette(&[Pixel::color(0, 0, 0xff)]).unwrap();
+    assert_eq!(rgb(one.get_pixel(0, 0)), (0, 0, 0xff));
+    assert_eq!(rgb(one.get_pixel(1, 0)), (0, 0, 0xff));
+}
+
+#[test]
+fn store_too_small() {
+    let buf = Buffer::new(4, 3);
+    match buf.try_store_pixels(&mut [0; 11], PixelFormat::RGBA) {
+        Err(Error::SliceTooSmall {
+            expected: 12,
+            found: 11,
+        }) => (),
+        r => panic!("unexpected {:?}", r),
+    }
+    match buf.try_store_bytes(&mut [0; 64], PixelFormat::RGBA, 15) {
+        Err(Error::InvalidPitch { pitch: 3, w: 4 }) => (),
+        r => panic!("unexpected {:?}", r),
+    }
+    match buf.try_store_bytes(&mut [0; 47], PixelFormat::RGBA, 16) {
+        Err(Error::SliceTooSmall {
+            expected: 48,
+            found: 47,
+        }) => (),
+        r => panic!("unexpected {:?}", r),
+    }
+    assert!(buf.try_store_pixels(&mut [0; 12], PixelFormat::RGBA).is_ok());
+    assert!(buf.try_store_bytes(&mut [0; 48], PixelFormat::RGBA, 16).is_ok());
+}
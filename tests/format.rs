@@ -0,0 +1,84 @@
+extern crate sera;
+
+use sera::*;
+
+#[test]
+fn load_store() {
+    let p = Pixel::pixel(0x11, 0x22, 0x33, 0x44);
+    for &(fmt, word) in &[
+        (PixelFormat::BGRA, 0x4411_2233),
+        (PixelFormat::RGBA, 0x4433_2211),
+        (PixelFormat::ARGB, 0x3322_1144),
+        (PixelFormat::ABGR, 0x1122_3344),
+    ] {
+        let stored = fmt.store(p);
+        assert!(stored == word, "{:?}: {:x}", fmt, unsafe { stored.word });
+        assert_eq!(fmt.load(stored), p, "{:?}", fmt);
+    }
+}
+
+#[test]
+fn load_pixels() {
+    let words = [0x4433_2211u32, 0xff00_00ff];
+    for &fmt in &[PixelFormat::BGRA, PixelFormat::RGBA, PixelFormat::ARGB] {
+        let mut buf = Buffer::new(2, 1);
+        buf.set_format(fmt);
+        buf.load_pixels(&words, PixelFormat::RGBA);
+        assert_eq!(buf.get_pixel(0, 0), Pixel::pixel(0x11, 0x22, 0x33, 0x44));
+        assert_eq!(buf.get_pixel(1, 0), Pixel::pixel(0xff, 0, 0, 0xff));
+    }
+}
+
+#[test]
+fn set_format() {
+    let mut buf = Buffer::new(3, 3);
+    buf.clear(Pixel::color(0x10, 0x20, 0x30));
+    buf.set_pixel(Pixel::pixel(1, 2, 3, 4), 1, 1);
+    buf.set_format(PixelFormat::ARGB);
+    assert!(buf.pixels[0] == 0x3020_10ff);
+    assert_eq!(buf.get_pixel(1, 1), Pixel::pixel(1, 2, 3, 4));
+    buf.set_format(PixelFormat::NATIVE);
+    assert_eq!(buf.get_pixel(0, 0), Pixel::color(0x10, 0x20, 0x30));
+}
+
+#[test]
+fn store_pixels() {
+    let mut buf = Buffer::new(2, 1);
+    buf.set_pixel(Pixel::pixel(0x11, 0x22, 0x33, 0x44), 0, 0);
+    buf.set_pixel(Pixel::pixel(0xff, 0, 0, 0xff), 1, 0);
+    for &fmt in &[PixelFormat::BGRA, PixelFormat::RGBA] {
+        buf.set_format(fmt);
+        let mut words = [0u32; 2];
+        buf.store_pixels(&mut words, PixelFormat::RGBA);
+        assert_eq!(words, [0x4433_2211, 0xff00_00ff]);
+        buf.store_pixels(&mut words, PixelFormat::ARGB);
+        assert_eq!(words, [0x3322_1144, 0x0000_ffff]);
+    }
+}
+
+#[test]
+fn store_bytes() {
+    let mut buf = Buffer::new(2, 2);
+    buf.clear(Pixel::pixel(1, 2, 3, 4));
+    buf.set_pixel(Pixel::pixel(5, 6, 7, 8), 1, 1);
+    buf.set_format(PixelFormat::ABGR);
+    /* Rows 12 bytes apart, the padding between them must be left alone */
+    let mut data = [0xaau8; 20];
+    buf.store_bytes(&mut data, PixelFormat::RGBA, 12);
+    assert_eq!(
+        data,
+        [
+            1, 2, 3, 4, 1, 2, 3, 4, 0xaa, 0xaa, 0xaa, 0xaa,
+            1, 2, 3, 4, 5, 6, 7, 8,
+        ]
+    );
+    buf.store_bytes(&mut data, PixelFormat::ARGB, 8);
+    assert_eq!(&data[..16], &[4, 1, 2, 3, 4, 1, 2, 3, 4, 1, 2, 3, 8, 5, 6, 7]);
+}
+
+#[test]
+#[should_panic(expected = "invalid pitch of 1 pixels, expected width of 2 or greater")]
+fn store_bytes_short_pitch() {
+    let buf = Buffer::new(2, 2);
+    buf.store_bytes(&mut [0; 64], PixelFormat::RGBA, 4);
+}
@@ -0,0 +1,1424 @@
+extern crate sera;
+
+use sera::*;
+use std::env;
+use std::f32;
+use std::fs;
+use std::path::PathBuf;
+
+/* Size of every rendered case */
+const SIZE: i32 = 64;
+
+/* Largest per-channel difference that still counts as a match */
+const TOLERANCE: i32 = 2;
+
+const BLEND_MODES: [(BlendMode, &str); 9] = [
+    (BlendMode::ALPHA, "alpha"),
+    (BlendMode::COLOR, "color"),
+    (BlendMode::ADD, "add"),
+    (BlendMode::SUBTRACT, "subtract"),
+    (BlendMode::MULTIPLY, "multiply"),
+    (BlendMode::LIGHTEN, "lighten"),
+    (BlendMode::DARKEN, "darken"),
+    (BlendMode::SCREEN, "screen"),
+    (BlendMode::DIFFERENCE, "difference"),
+];
+
+const PIXEL_FORMATS: [PixelFormat; 4] = [
+    PixelFormat::BGRA,
+    PixelFormat::RGBA,
+    PixelFormat::ARGB,
+    PixelFormat::ABGR,
+];
+
+fn channels(p: Pixel) -> [u8; 4] {
+    unsafe { [p.rgba.r, p.rgba.g, p.rgba.b, p.rgba.a] }
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.tga", name))
+}
+
+fn diff_path(name: &str, suffix: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/golden");
+    fs::create_dir_all(&dir).unwrap();
+    dir.join(format!("{}.{}.tga", name, suffix))
+}
+
+/* Compares `buf` against `tests/golden/<name>.tga`. Set `SERA_BLESS=1` to
+ * (re)generate the reference images instead. On a mismatch the rendered image
+ * and a diff image (mismatching pixels in red) are written to `target/golden` */
+fn check(name: &str, buf: &Buffer) {
+    let path = golden_path(name);
+    if env::var_os("SERA_BLESS").is_some() {
+        buf.save(&path, ImageFormat::TGA).unwrap();
+        return;
+    }
+    assert_eq!(buf.fmt, PixelFormat::NATIVE, "{}: compare native buffers only", name);
+    let expected = match Buffer::file(&path) {
+        Ok(b) => b,
+        Err(e) => panic!("{:?}: {}, run with SERA_BLESS=1 to generate it", path, e),
+    };
+    assert_eq!(expected.get_size(), buf.get_size(), "{}: size mismatch", name);
+    let mut diff = Buffer::new(buf.w, buf.h);
+    let mut bad = 0;
+    for (i, (e, a)) in expected.pixels.iter().zip(buf.pixels.iter()).enumerate() {
+        let (e, a) = (channels(*e), channels(*a));
+        let ok = e.iter()
+            .zip(a.iter())
+            .all(|(e, a)| (i32::from(*e) - i32::from(*a)).abs() <= TOLERANCE);
+        diff.pixels[i] = if ok {
+            Pixel::color(e[0] / 4, e[1] / 4, e[2] / 4)
+        } else {
+            bad += 1;
+            Pixel::color(0xff, 0, 0)
+        };
+    }
+    if bad > 0 {
+        buf.save(diff_path(name, "actual"), ImageFormat::TGA).unwrap();
+        diff.save(diff_path(name, "diff"), ImageFormat::TGA).unwrap();
+        panic!(
+            "{}: {} pixels differ from reference, see {:?}",
+            name,
+            bad,
+            diff_path(name, "diff")
+        );
+    }
+}
+
+fn canvas() -> Buffer {
+    let mut buf = Buffer::new(SIZE, SIZE);
+    buf.clear(Pixel::color(0x20, 0x30, 0x40));
+    buf
+}
+
+/* An asymmetric 16x12 sprite so flips and rotations are visible */
+fn sprite() -> Buffer {
+    let mut buf = Buffer::new(16, 12);
+    for y in 0..buf.h {
+        for x in 0..buf.w {
+            let a = if x == 0 || y == 0 { 0x80 } else { 0xff };
+            buf.set_pixel(
+                Pixel::pixel((x * 16) as u8, (y * 21) as u8, 0xc0, a),
+                x,
+                y,
+            );
+        }
+    }
+    buf.set_pixel(Pixel::color(0xff, 0xff, 0xff), 15, 11);
+    buf
+}
+
+/* Vertical bands of differing brightness to blend onto */
+fn bands() -> Buffer {
+    let mut buf = canvas();
+    for i in 0..8 {
+        let v = (i * 36) as u8;
+        buf.draw_rect(Pixel::color(v, 0xff - v, v / 2), i * 8, 0, 8, SIZE);
+    }
+    buf
+}
+
+#[test]
+fn pixel() {
+    let mut buf = canvas();
+    for i in -2..(SIZE + 2) {
+        buf.draw_pixel(Pixel::color(0xff, 0xff, 0), i, i);
+        buf.draw_pixel(Pixel::color(0, 0xff, 0xff), SIZE - i, i / 2);
+    }
+    check("pixel", &buf);
+}
+
+#[test]
+fn line() {
+    let mut buf = canvas();
+    let c = [SIZE / 2, SIZE / 2];
+    for (i, &(x, y)) in [
+        (60, 32), (60, 50), (50, 60), (32, 60), (14, 60), (4, 50),
+        (4, 32), (4, 14), (14, 4), (32, 4), (50, 4), (60, 14),
+    ].iter()
+        .enumerate()
+    {
+        let v = (i * 20) as u8;
+        buf.draw_line(Pixel::color(0xff, v, 0xff - v), c[0], c[1], x, y);
+    }
+    buf.draw_line(Pixel::color(0xff, 0xff, 0xff), -10, 70, 70, -20);
+    check("line", &buf);
+}
+
+#[test]
+fn rect() {
+    let mut buf = canvas();
+    buf.draw_rect(Pixel::color(0xff, 0, 0), 4, 4, 20, 12);
+    buf.draw_rect(Pixel::pixel(0, 0xff, 0, 0x80), 14, 10, 20, 20);
+    buf.draw_rect(Pixel::color(0, 0, 0xff), -8, 40, 24, 40);
+    buf.draw_rect(Pixel::color(0xff, 0xff, 0), 50, 50, 0, 10);
+    check("rect", &buf);
+}
+
+#[test]
+fn box_() {
+    let mut buf = canvas();
+    buf.draw_box(Pixel::color(0xff, 0, 0), 4, 4, 20, 12);
+    buf.draw_box(Pixel::pixel(0, 0xff, 0, 0x80), 14, 10, 20, 20);
+    buf.draw_box(Pixel::color(0, 0, 0xff), -8, 40, 24, 40);
+    buf.draw_box(Pixel::color(0xff, 0xff, 0), 50, 50, 1, 1);
+    check("box", &buf);
+}
+
+#[test]
+fn circle() {
+    let mut buf = canvas();
+    buf.draw_circle(Pixel::color(0xff, 0, 0), 20, 20, 14);
+    buf.draw_circle(Pixel::pixel(0, 0xff, 0, 0x80), 32, 32, 10);
+    buf.draw_circle(Pixel::color(0, 0, 0xff), 60, 60, 20);
+    buf.draw_circle(Pixel::color(0xff, 0xff, 0), 10, 54, 1);
+    check("circle", &buf);
+}
+
+#[test]
+fn ring() {
+    let mut buf = canvas();
+    buf.draw_ring(Pixel::color(0xff, 0, 0), 20, 20, 14);
+    buf.draw_ring(Pixel::pixel(0, 0xff, 0, 0x80), 32, 32, 10);
+    buf.draw_ring(Pixel::color(0, 0, 0xff), 60, 60, 20);
+    buf.draw_ring(Pixel::color(0xff, 0xff, 0), 10, 54, 1);
+    check("ring", &buf);
+}
+
+#[test]
+fn line_aa() {
+    let mut buf = canvas();
+    for i in 0..12 {
+        let a = i as f32 * f32::consts::PI / 6.0;
+        let v = (i * 20) as u8;
+        let (x, y) = (32.0 + a.cos() * 28.0, 32.0 + a.sin() * 28.0);
+        buf.draw_line_aa(Pixel::color(0xff, v, 0xff - v), 32.0, 32.0, x, y);
+    }
+    buf.set_alpha(0x80);
+    buf.draw_line_aa(Pixel::color(0xff, 0xff, 0xff), -10.0, 70.0, 70.5, -20.25);
+    buf.draw_line_aa(Pixel::color(0xff, 0xff, 0), 2.5, 60.0, 3.0, 60.0);
+    check("line_aa", &buf);
+}
+
+#[test]
+fn line_aa_point() {
+    let mut expected = canvas();
+    expected.draw_line(Pixel::color(0xff, 0x80, 0), 12, 20, 12, 20);
+    expected.draw_line(Pixel::color(0, 0xff, 0), 40, 7, 40, 7);
+    let mut buf = canvas();
+    buf.draw_line_aa(Pixel::color(0xff, 0x80, 0), 12.0, 20.0, 12.0, 20.0);
+    buf.set_antialias(true);
+    buf.draw_line(Pixel::color(0, 0xff, 0), 40, 7, 40, 7);
+    assert_eq!(buf.pixels, expected.pixels);
+}
+
+#[test]
+fn circle_aa() {
+    let mut buf = canvas();
+    buf.draw_circle_aa(Pixel::color(0xff, 0, 0), 20.0, 20.0, 14.0);
+    buf.draw_circle_aa(Pixel::pixel(0, 0xff, 0, 0x80), 32.5, 32.5, 10.0);
+    buf.draw_circle_aa(Pixel::color(0, 0, 0xff), 60.0, 60.0, 20.0);
+    buf.draw_circle_aa(Pixel::color(0xff, 0xff, 0), 10.25, 54.75, 1.5);
+    check("circle_aa", &buf);
+}
+
+#[test]
+fn ring_aa() {
+    let mut buf = canvas();
+    buf.draw_ring_aa(Pixel::color(0xff, 0, 0), 20.0, 20.0, 14.0);
+    buf.draw_ring_aa(Pixel::pixel(0, 0xff, 0, 0x80), 32.5, 32.5, 10.0);
+    buf.draw_ring_aa(Pixel::color(0, 0, 0xff), 60.0, 60.0, 20.0);
+    buf.draw_ring_aa(Pixel::color(0xff, 0xff, 0), 10.25, 54.75, 1.5);
+    check("ring_aa", &buf);
+}
+
+#[test]
+fn antialias_flag() {
+    /* Setting the flag on the mode switches the integer calls over */
+    let mut expected = canvas();
+    expected.draw_line_aa(Pixel::color(0xff, 0x80, 0), 3.0, 5.0, 60.0, 41.0);
+    expected.draw_circle_aa(Pixel::color(0, 0xff, 0), 20.0, 44.0, 9.0);
+    expected.draw_ring_aa(Pixel::color(0, 0x80, 0xff), 44.0, 20.0, 12.0);
+    let mut buf = canvas();
+    buf.set_antialias(true);
+    buf.draw_line(Pixel::color(0xff, 0x80, 0), 3, 5, 60, 41);
+    buf.draw_circle(Pixel::color(0, 0xff, 0), 20, 44, 9);
+    buf.draw_ring(Pixel::color(0, 0x80, 0xff), 44, 20, 12);
+    assert_eq!(buf.pixels, expected.pixels);
+    buf.reset();
+    assert!(!buf.mode.antialias);
+}
+
+#[test]
+fn thick_line() {
+    let mut buf = canvas();
+    let caps = [LineCap::BUTT, LineCap::ROUND, LineCap::SQUARE];
+    for (i, &cap) in caps.iter().enumerate() {
+        let y = 8 + i as i32 * 10;
+        let style = LineStyle::new(6.0, cap, LineJoin::MITER);
+        buf.draw_thick_line(Pixel::color(0xff, 0x80, 0x40), 8, y, 40, y + 4, style);
+        buf.draw_pixel(Pixel::color(0, 0, 0), 8, y);
+        buf.draw_pixel(Pixel::color(0, 0, 0), 40, y + 4);
+    }
+    let style = LineStyle::new(3.0, LineCap::ROUND, LineJoin::MITER);
+    buf.draw_thick_line(Pixel::pixel(0, 0xff, 0x80, 0x80), 50, 4, 36, 60, style);
+    let style = LineStyle::new(1.0, LineCap::SQUARE, LineJoin::MITER);
+    buf.draw_thick_line(Pixel::color(0xff, 0xff, 0xff), -10, 70, 70, 40, style);
+    buf.draw_thick_line(Pixel::color(0xff, 0xff, 0), 10, 56, 10, 56, style);
+    check("thick_line", &buf);
+}
+
+#[test]
+fn polyline() {
+    let mut buf = canvas();
+    let joins = [LineJoin::MITER, LineJoin::ROUND, LineJoin::BEVEL];
+    for (i, &join) in joins.iter().enumerate() {
+        let x = 4 + i as i32 * 20;
+        let points = [
+            Point::new(x, 56),
+            Point::new(x + 6, 8),
+            Point::new(x + 14, 40),
+            Point::new(x + 10, 44),
+        ];
+        let style = LineStyle::new(5.0, LineCap::BUTT, join);
+        buf.draw_polyline(Pixel::pixel(0xff, 0xc0, 0x40, 0xc0), &points, style);
+    }
+    check("polyline", &buf);
+}
+
+#[test]
+fn polyline_overdraw() {
+    /* Overlapping segments, caps and joins must not be blended twice */
+    let points = [
+        Point::new(8, 8),
+        Point::new(56, 8),
+        Point::new(12, 40),
+        Point::new(56, 56),
+        Point::new(20, 6),
+    ];
+    for &(join, cap) in &[
+        (LineJoin::MITER, LineCap::SQUARE),
+        (LineJoin::ROUND, LineCap::ROUND),
+        (LineJoin::BEVEL, LineCap::BUTT),
+    ] {
+        let mut buf = canvas();
+        let bg = buf.get_pixel(0, 0);
+        buf.set_blend(BlendMode::ADD);
+        let style = LineStyle::new(7.0, cap, join);
+        buf.draw_polyline(Pixel::pixel(0x40, 0x40, 0x40, 0xff), &points, style);
+        let mut n = 0;
+        for p in &buf.pixels {
+            if *p != bg {
+                assert_eq!(*p, Pixel::color(0x60, 0x70, 0x80), "{:?} {:?}", join, cap);
+                n += 1;
+            }
+        }
+        assert!(n > 0);
+    }
+}
+
+/* A five pointed star, its center is only filled under the non-zero rule */
+fn star(cx: i32, cy: i32, r: f32) -> Vec<Point> {
+    (0..5)
+        .map(|i| {
+            let a = (i * 2 % 5) as f32 * 2.0 * f32::consts::PI / 5.0 - f32::consts::PI / 2.0;
+            Point::new(cx + (a.cos() * r) as i32, cy + (a.sin() * r) as i32)
+        })
+        .collect()
+}
+
+#[test]
+fn fill_polygon() {
+    let mut buf = canvas();
+    buf.fill_polygon(Pixel::color(0xff, 0xc0, 0), &star(16, 18, 14.0), FillRule::EVENODD);
+    buf.fill_polygon(Pixel::color(0xff, 0xc0, 0), &star(48, 18, 14.0), FillRule::NONZERO);
+    let arrow = [
+        Point::new(4, 44),
+        Point::new(24, 44),
+        Point::new(24, 36),
+        Point::new(40, 50),
+        Point::new(24, 64),
+        Point::new(24, 56),
+        Point::new(4, 56),
+    ];
+    buf.fill_polygon(Pixel::pixel(0, 0xff, 0x80, 0x80), &arrow, FillRule::NONZERO);
+    let triangle = [Point::new(44, 40), Point::new(70, 70), Point::new(36, 60)];
+    buf.fill_polygon(Pixel::color(0x40, 0x80, 0xff), &triangle, FillRule::EVENODD);
+    check("fill_polygon", &buf);
+}
+
+#[test]
+fn fill_polygon_rect() {
+    /* An axis aligned polygon covers the same pixels as `draw_rect` */
+    let mut expected = canvas();
+    expected.set_clip(Rect::new(4, 4, 50, 50));
+    let mut buf = expected.clone();
+    expected.draw_rect(Pixel::color(0xff, 0, 0), -3, 10, 20, 30);
+    let rect = [
+        Point::new(-3, 10),
+        Point::new(17, 10),
+        Point::new(17, 40),
+        Point::new(-3, 40),
+    ];
+    buf.fill_polygon(Pixel::color(0xff, 0, 0), &rect, FillRule::EVENODD);
+    assert_eq!(buf.pixels, expected.pixels);
+}
+
+#[test]
+fn draw_polygon() {
+    let mut buf = canvas();
+    buf.set_blend(BlendMode::ADD);
+    let c = Pixel::color(0x60, 0x60, 0x60);
+    buf.draw_polygon(c, &star(16, 18, 14.0));
+    buf.draw_polygon(c, &[Point::new(40, 4), Point::new(60, 12), Point::new(44, 30)]);
+    buf.draw_polygon(c, &[Point::new(8, 40), Point::new(30, 60)]);
+    buf.draw_polygon(c, &[Point::new(50, 50), Point::new(50, 50)]);
+    buf.set_antialias(true);
+    buf.draw_polygon(c, &star(46, 46, 14.0));
+    check("draw_polygon", &buf);
+}
+
+#[test]
+fn draw_polygon_overdraw() {
+    /* Vertices shared by two edges are only drawn once */
+    let mut buf = canvas();
+    let bg = buf.get_pixel(0, 0);
+    buf.set_blend(BlendMode::ADD);
+    let points = [
+        Point::new(4, 4),
+        Point::new(60, 10),
+        Point::new(30, 30),
+        Point::new(58, 58),
+        Point::new(6, 50),
+    ];
+    buf.draw_polygon(Pixel::color(0x40, 0x40, 0x40), &points);
+    for p in &points {
+        assert_eq!(buf.get_pixel(p.x, p.y), Pixel::color(0x60, 0x70, 0x80));
+    }
+    for p in &buf.pixels {
+        assert!(*p == bg || *p == Pixel::color(0x60, 0x70, 0x80));
+    }
+}
+
+#[test]
+fn ellipse() {
+    let mut buf = canvas();
+    buf.fill_ellipse(Pixel::color(0xff, 0, 0), 20, 14, 16, 9);
+    buf.fill_ellipse(Pixel::pixel(0, 0xff, 0, 0x80), 32, 32, 6, 20);
+    buf.fill_ellipse(Pixel::color(0, 0, 0xff), 60, 60, 24, 12);
+    buf.fill_ellipse(Pixel::color(0xff, 0xff, 0), 6, 58, 4, 0);
+    buf.draw_ellipse(Pixel::color(0xff, 0xff, 0xff), 20, 14, 18, 11);
+    buf.draw_ellipse(Pixel::color(0xff, 0x80, 0), 44, 40, 16, 6);
+    buf.draw_ellipse(Pixel::color(0xff, 0x80, 0), 12, 44, 1, 6);
+    check("ellipse", &buf);
+}
+
+#[test]
+fn arc_pie() {
+    let mut buf = canvas();
+    let pi = f32::consts::PI;
+    buf.fill_pie(Pixel::color(0xff, 0, 0), 18, 18, 14, 0.0, pi / 2.0);
+    buf.fill_pie(Pixel::color(0, 0xff, 0), 18, 18, 14, pi, 1.75 * pi);
+    buf.fill_pie(Pixel::pixel(0, 0x80, 0xff, 0x80), 46, 46, 16, -pi / 3.0, pi / 3.0);
+    buf.fill_pie(Pixel::color(0xff, 0xff, 0), 46, 18, 12, 1.5 * pi, 1.0);
+    buf.draw_arc(Pixel::color(0xff, 0xff, 0xff), 18, 18, 16, 0.25 * pi, 1.25 * pi);
+    buf.draw_arc(Pixel::color(0xff, 0x80, 0), 18, 46, 12, 0.0, 1.5 * pi);
+    buf.draw_arc(Pixel::color(0xff, 0x80, 0), 18, 46, 8, 1.0, 8.0);
+    check("arc_pie", &buf);
+}
+
+#[test]
+fn ellipse_overdraw() {
+    /* Every pixel is blended once, and full turns match whole ellipses */
+    let pi = f32::consts::PI;
+    let c = Pixel::color(0x40, 0x40, 0x40);
+    let mut expected = canvas();
+    expected.set_blend(BlendMode::ADD);
+    let mut buf = expected.clone();
+    expected.fill_ellipse(c, 20, 20, 12, 12);
+    expected.draw_ellipse(c, 44, 44, 12, 12);
+    expected.fill_ellipse(c, 46, 16, 10, 5);
+    expected.draw_ellipse(c, 16, 48, 5, 14);
+    buf.fill_pie(c, 20, 20, 12, 1.0, 1.0 + 2.0 * pi);
+    buf.draw_arc(c, 44, 44, 12, -pi, pi);
+    buf.fill_ellipse(c, 46, 16, 10, 5);
+    buf.draw_ellipse(c, 16, 48, 5, 14);
+    assert_eq!(buf.pixels, expected.pixels);
+    let bg = Pixel::color(0x20, 0x30, 0x40);
+    for p in &buf.pixels {
+        assert!(*p == bg || *p == Pixel::color(0x60, 0x70, 0x80));
+    }
+}
+
+/* Circle of radius `r` out of four cubic curves, wound clockwise or not */
+fn circle_path(path: &mut Path, x: f32, y: f32, r: f32, clockwise: bool) {
+    let k = r * 0.552_284_8;
+    let s = if clockwise { 1.0 } else { -1.0 };
+    path.move_to(x + r, y)
+        .cubic_to(x + r, y + k * s, x + k, y + r * s, x, y + r * s)
+        .cubic_to(x - k, y + r * s, x - r, y + k * s, x - r, y)
+        .cubic_to(x - r, y - k * s, x - k, y - r * s, x, y - r * s)
+        .cubic_to(x + k, y - r * s, x + r, y - k * s, x + r, y)
+        .close();
+}
+
+#[test]
+fn bezier() {
+    let mut buf = canvas();
+    let c = Pixel::color(0xff, 0xc0, 0x40);
+    buf.draw_bezier_quad(c, &[Point::new(4, 60), Point::new(16, -20), Point::new(30, 60)]);
+    buf.draw_bezier_cubic(
+        c,
+        &[Point::new(34, 8), Point::new(90, 20), Point::new(0, 40), Point::new(60, 60)],
+    );
+    buf.set_antialias(true);
+    buf.draw_bezier_cubic(
+        Pixel::color(0x40, 0xc0, 0xff),
+        &[Point::new(2, 2), Point::new(62, 2), Point::new(2, 62), Point::new(62, 62)],
+    );
+    check("bezier", &buf);
+}
+
+#[test]
+fn fill_path() {
+    let mut buf = canvas();
+    /* Rings where the inner circle is a hole under either rule */
+    let mut ring = Path::new();
+    circle_path(&mut ring, 16.0, 16.0, 13.0, true);
+    circle_path(&mut ring, 16.0, 16.0, 7.0, false);
+    buf.fill_path(Pixel::color(0xff, 0, 0), &ring, FillRule::NONZERO);
+    let mut ring = Path::new();
+    circle_path(&mut ring, 46.5, 16.5, 13.0, true);
+    circle_path(&mut ring, 46.5, 16.5, 7.0, true);
+    buf.fill_path(Pixel::color(0, 0xff, 0), &ring, FillRule::EVENODD);
+    /* A heart, left open as filling closes it anyway */
+    let mut heart = Path::new();
+    heart
+        .move_to(16.0, 60.0)
+        .cubic_to(-8.0, 44.0, 6.0, 26.0, 16.0, 40.0)
+        .quad_to(22.0, 30.0, 28.0, 36.0)
+        .cubic_to(32.0, 42.0, 28.0, 50.0, 16.0, 60.0);
+    buf.fill_path(Pixel::pixel(0xff, 0x40, 0xc0, 0xc0), &heart, FillRule::NONZERO);
+    let mut shapes = Path::new();
+    shapes.move_to(36.0, 36.0).line_to(60.0, 40.0).line_to(40.0, 60.0);
+    shapes.move_to(50.0, 56.0).quad_to(70.0, 30.0, 62.0, 62.0).close();
+    buf.fill_path(Pixel::color(0x40, 0x80, 0xff), &shapes, FillRule::NONZERO);
+    check("fill_path", &buf);
+}
+
+#[test]
+fn stroke_path() {
+    let mut buf = canvas();
+    let mut path = Path::new();
+    circle_path(&mut path, 20.0, 20.0, 14.0, true);
+    path.move_to(40.0, 8.0)
+        .quad_to(64.0, 8.0, 56.0, 30.0)
+        .line_to(40.0, 30.0)
+        .close()
+        .line_to(60.0, 60.0)
+        .cubic_to(30.0, 70.0, 30.0, 30.0, 8.0, 56.0);
+    let style = LineStyle::new(4.0, LineCap::ROUND, LineJoin::MITER);
+    buf.stroke_path(Pixel::pixel(0xff, 0xc0, 0x40, 0xc0), &path, style);
+    buf.draw_path(Pixel::color(0, 0, 0), &path);
+    check("stroke_path", &buf);
+}
+
+#[test]
+fn fill_path_polygon() {
+    /* Straight paths fill exactly like polygons */
+    let points = [Point::new(4, 4), Point::new(60, 20), Point::new(10, 58), Point::new(34, 2)];
+    let mut path = Path::new();
+    path.move_to(4.0, 4.0);
+    for p in &points[1..] {
+        path.line_to(p.x as f32, p.y as f32);
+    }
+    for &rule in &[FillRule::EVENODD, FillRule::NONZERO] {
+        let mut expected = canvas();
+        expected.fill_polygon(Pixel::color(0xff, 0, 0), &points, rule);
+        let mut buf = canvas();
+        buf.fill_path(Pixel::color(0xff, 0, 0), &path, rule);
+        assert_eq!(buf.pixels, expected.pixels);
+    }
+}
+
+#[test]
+fn rounded_rect() {
+    let mut buf = canvas();
+    buf.fill_rounded_rect(Pixel::color(0xff, 0, 0), 4, 4, 26, 18, [6, 6, 6, 6]);
+    buf.fill_rounded_rect(Pixel::pixel(0, 0xff, 0, 0x80), 20, 14, 30, 20, [0, 12, 3, 40]);
+    buf.fill_rounded_rect(Pixel::color(0, 0, 0xff), 40, 44, 40, 30, [10, 10, 10, 10]);
+    buf.draw_rounded_box(Pixel::color(0xff, 0xff, 0xff), 2, 2, 30, 22, [8, 8, 8, 8]);
+    buf.draw_rounded_box(Pixel::color(0xff, 0xc0, 0), 4, 38, 30, 22, [2, 11, 0, 6]);
+    buf.draw_rounded_box(Pixel::color(0xff, 0xc0, 0), 52, 4, 8, 30, [4, 4, 4, 4]);
+    check("rounded_rect", &buf);
+}
+
+#[test]
+fn rounded_rect_shapes() {
+    /* Without radii they match `draw_rect` and `draw_box`, and at full radius
+     * a square becomes `fill_ellipse` */
+    let mut expected = canvas();
+    let mut buf = canvas();
+    expected.draw_rect(Pixel::color(0xff, 0, 0), 4, 4, 20, 12);
+    expected.draw_box(Pixel::color(0, 0xff, 0), 30, 4, 20, 12);
+    expected.fill_ellipse(Pixel::color(0, 0, 0xff), 20, 40, 9, 9);
+    buf.fill_rounded_rect(Pixel::color(0xff, 0, 0), 4, 4, 20, 12, [0; 4]);
+    buf.draw_rounded_box(Pixel::color(0, 0xff, 0), 30, 4, 20, 12, [0; 4]);
+    buf.fill_rounded_rect(Pixel::color(0, 0, 0xff), 11, 31, 19, 19, [9; 4]);
+    assert_eq!(buf.pixels, expected.pixels);
+
+    /* Outlines blend every pixel once */
+    let mut buf = canvas();
+    let bg = buf.get_pixel(0, 0);
+    buf.set_blend(BlendMode::ADD);
+    buf.draw_rounded_box(Pixel::color(0x40, 0x40, 0x40), 4, 4, 50, 40, [20, 3, 12, 7]);
+    for p in &buf.pixels {
+        assert!(*p == bg || *p == Pixel::color(0x60, 0x70, 0x80));
+    }
+}
+
+fn rainbow(g: &mut Gradient) -> &mut Gradient {
+    g.add_stop(0.0, Pixel::color(0xff, 0, 0))
+        .add_stop(0.5, Pixel::pixel(0, 0xff, 0, 0x80))
+        .add_stop(1.0, Pixel::color(0, 0, 0xff))
+}
+
+#[test]
+fn gradient() {
+    let mut buf = canvas();
+    let mut linear = Gradient::linear(4.0, 4.0, 28.0, 12.0);
+    rainbow(&mut linear);
+    buf.draw_rect(&linear, 0, 0, 32, 20);
+    let mut repeat = Gradient::linear(36.0, 0.0, 44.0, 0.0);
+    rainbow(&mut repeat).set_spread(Spread::REPEAT);
+    buf.draw_rect(&repeat, 32, 0, 32, 10);
+    let mut reflect = Gradient::linear(36.0, 0.0, 44.0, 0.0);
+    rainbow(&mut reflect).set_spread(Spread::REFLECT);
+    buf.draw_rect(&reflect, 32, 10, 32, 10);
+
+    let mut radial = Gradient::radial(16.0, 38.0, 12.0);
+    radial
+        .add_stop(0.0, Pixel::color(0xff, 0xff, 0xff))
+        .add_stop(0.6, Pixel::color(0xff, 0x80, 0))
+        .add_stop(0.6, Pixel::color(0x80, 0, 0xff))
+        .add_stop(1.0, Pixel::pixel(0, 0, 0, 0));
+    buf.draw_circle(&radial, 16, 38, 14);
+    let mut conic = Gradient::conic(46.0, 42.0, 0.5);
+    rainbow(&mut conic);
+    buf.fill_polygon(
+        &conic,
+        &[Point::new(30, 24), Point::new(62, 28), Point::new(54, 62), Point::new(34, 56)],
+        FillRule::NONZERO,
+    );
+    let mut pad = Gradient::radial(10.0, 58.0, 3.0);
+    rainbow(&mut pad);
+    buf.fill_rounded_rect(&pad, 2, 52, 24, 12, [5; 4]);
+    check("gradient", &buf);
+}
+
+#[test]
+fn gradient_stops() {
+    let mut g = Gradient::linear(0.0, 0.0, 10.0, 0.0);
+    g.add_stop(0.25, Pixel::color(0, 0, 0)).add_stop(0.75, Pixel::color(0xff, 0xff, 0xff));
+    assert_eq!(g.color_at(-1.0), Pixel::color(0, 0, 0));
+    assert_eq!(g.color_at(0.1), Pixel::color(0, 0, 0));
+    /* Colors come from a table, so allow for rounding in between stops */
+    let grey = |p: Pixel| {
+        let c = channels(p);
+        c[0] == c[1] && c[1] == c[2] && (i32::from(c[0]) - 0x80).abs() <= 1
+    };
+    assert!(grey(g.color_at(0.5)));
+    assert_eq!(g.color_at(2.0), Pixel::color(0xff, 0xff, 0xff));
+    g.set_spread(Spread::REPEAT);
+    assert_eq!(g.color_at(1.1), Pixel::color(0, 0, 0));
+    g.set_spread(Spread::REFLECT);
+    assert_eq!(g.color_at(1.1), Pixel::color(0xff, 0xff, 0xff));
+    assert!(grey(g.color_at(-0.5)));
+}
+
+fn checker() -> Buffer {
+    let mut src = Buffer::new(8, 8);
+    src.clear(Pixel::color(0xc0, 0xc0, 0xc0));
+    src.draw_rect(Pixel::color(0x60, 0x60, 0x60), 0, 0, 4, 4);
+    src.draw_rect(Pixel::color(0x60, 0x60, 0x60), 4, 4, 4, 4);
+    src
+}
+
+#[test]
+fn pattern() {
+    let mut buf = canvas();
+    let checker = checker();
+    let mut tiles = Pattern::new(&checker);
+    tiles.set_offset(2, 2);
+    buf.draw_rect(&tiles, 0, 0, 32, 24);
+    let sprite = sprite();
+    let mut sprites = Pattern::new(&sprite);
+    buf.draw_circle(&sprites, 48, 12, 11);
+    sprites.set_transform(Some(Transform::new(0.0, 0.0, 0.6, 1.5, 1.5)));
+    buf.fill_polygon(
+        &sprites,
+        &[Point::new(4, 28), Point::new(60, 30), Point::new(40, 62), Point::new(2, 56)],
+        FillRule::NONZERO,
+    );
+    /* Alpha in the source blends like any other paint */
+    let mut hatch = Buffer::new(4, 4);
+    hatch.clear(Pixel::pixel(0, 0, 0, 0));
+    hatch.draw_line(Pixel::pixel(0xff, 0xff, 0, 0xc0), 0, 3, 3, 0);
+    buf.draw_rect(&Pattern::new(&hatch), 8, 8, 48, 48);
+    check("pattern", &buf);
+}
+
+#[test]
+fn pattern_tiles() {
+    let checker = checker();
+    let mut p = Pattern::new(&checker);
+    p.set_offset(-3, 5);
+    let mut a = Buffer::new(24, 24);
+    a.draw_rect(&p, 0, 0, 24, 24);
+    for y in 0..24 {
+        for x in 0..24 {
+            let (sx, sy) = ((x + 3) % 8, (y + 3) % 8);
+            assert_eq!(a.get_pixel(x, y), checker.get_pixel(sx, sy), "({}, {})", x, y);
+        }
+    }
+    /* Doubling the scale doubles the tile size */
+    p.set_offset(0, 0).set_transform(Some(Transform::new(0.0, 0.0, 0.0, 2.0, 2.0)));
+    a.draw_rect(&p, 0, 0, 24, 24);
+    for y in 0..24 {
+        for x in 0..24 {
+            let (sx, sy) = ((x / 2) % 8, (y / 2) % 8);
+            assert_eq!(a.get_pixel(x, y), checker.get_pixel(sx, sy), "({}, {})", x, y);
+        }
+    }
+}
+
+#[test]
+fn clip_mask() {
+    let mut buf = canvas();
+    let mut viewport = Buffer::new(SIZE, SIZE);
+    viewport.clear(Pixel::pixel(0, 0, 0, 0));
+    viewport.set_antialias(true);
+    viewport.draw_circle(Pixel::color(0xff, 0xff, 0xff), 32, 32, 26);
+    buf.push_clip_mask(&viewport, None);
+    buf.draw_rect(Pixel::color(0x80, 0x80, 0x80), 0, 0, SIZE, SIZE);
+    for i in 0..8 {
+        buf.draw_line(Pixel::color(0xff, 0, 0), 0, i * 8, SIZE - 1, SIZE - 1 - i * 8);
+    }
+    buf.draw(&sprite(), 40, 20, None, Some(Transform::new(0.0, 0.0, 0.0, 2.0, 2.0)));
+    /* Nested masks only draw where both are set */
+    let mut stripes = Buffer::new(SIZE, SIZE);
+    stripes.clear(Pixel::pixel(0, 0, 0, 0));
+    for x in (0..SIZE).step_by(4) {
+        stripes.draw_rect(Pixel::color(0xff, 0, 0), x, 0, 2, SIZE);
+    }
+    buf.push_clip_mask(&stripes, Some(ColorChannel::R));
+    buf.draw_rect(Pixel::color(0, 0xff, 0), 0, 36, SIZE, 20);
+    assert!(buf.pop_clip_mask());
+    /* Partial coverage draws partially */
+    let mut fade = Buffer::new(SIZE, SIZE);
+    let mut g = Gradient::linear(0.0, 0.0, SIZE as f32, 0.0);
+    g.add_stop(0.0, Pixel::pixel(0, 0, 0, 0)).add_stop(1.0, Pixel::color(0, 0, 0));
+    fade.clear(Pixel::pixel(0, 0, 0, 0));
+    fade.draw_rect(&g, 0, 0, SIZE, SIZE);
+    buf.push_clip_mask(&fade, None);
+    buf.draw_rect(Pixel::color(0, 0, 0xff), 0, 4, SIZE, 10);
+    assert!(buf.pop_clip_mask());
+    assert!(buf.pop_clip_mask());
+    assert!(!buf.pop_clip_mask());
+    buf.draw_box(Pixel::color(0xff, 0xff, 0), 2, 2, SIZE - 4, SIZE - 4);
+    check("clip_mask", &buf);
+}
+
+#[test]
+fn clip_mask_resize() {
+    let mut buf = Buffer::new(16, 16);
+    let mut mask = Buffer::new(16, 16);
+    mask.clear(Pixel::pixel(0, 0, 0, 0));
+    mask.draw_rect(Pixel::color(0xff, 0xff, 0xff), 0, 0, 8, 16);
+    buf.push_clip_mask(&mask, None);
+    /* Resizing drops the masks, which no longer fit the buffer */
+    buf.resize(SIZE, SIZE);
+    assert!(!buf.pop_clip_mask());
+    buf.draw_rect(Pixel::color(0xff, 0, 0), 0, 0, SIZE, SIZE);
+    let mut expected = Buffer::new(SIZE, SIZE);
+    expected.draw_rect(Pixel::color(0xff, 0, 0), 0, 0, SIZE, SIZE);
+    assert_eq!(buf, expected);
+}
+
+#[test]
+fn save_restore_resize() {
+    let mut buf = Buffer::new(32, 32);
+    buf.save_state();
+    /* Resizing drops the saved states, whose clip rect no longer fits */
+    buf.resize(8, 8);
+    assert!(!buf.restore_state());
+    buf.draw_rect(Pixel::color(0xff, 0, 0), 0, 0, 32, 32);
+    let mut expected = Buffer::new(8, 8);
+    expected.draw_rect(Pixel::color(0xff, 0, 0), 0, 0, 8, 8);
+    assert_eq!(buf, expected);
+}
+
+#[test]
+fn save_restore() {
+    let mut buf = canvas();
+    let (mode, clip) = (buf.mode, buf.clip);
+    buf.save_state();
+    buf.set_blend(BlendMode::ADD);
+    buf.set_alpha(0x40);
+    buf.intersect_clip(Rect::new(-8, 4, 40, 40));
+    assert_eq!(buf.clip, Rect::new(0, 4, 32, 40));
+    buf.save_state();
+    buf.set_color(Pixel::color(0xff, 0, 0));
+    buf.intersect_clip(Rect::new(16, 30, 40, 40));
+    assert_eq!(buf.clip, Rect::new(16, 30, 16, 14));
+    let mut mask = Buffer::new(SIZE, SIZE);
+    mask.clear(Pixel::pixel(0, 0, 0, 0));
+    buf.push_clip_mask(&mask, None);
+    assert!(buf.restore_state());
+    /* The inner changes are undone, including the pushed mask */
+    assert_eq!(buf.clip, Rect::new(0, 4, 32, 40));
+    assert_eq!(buf.mode.blend, BlendMode::ADD);
+    assert_eq!(buf.mode.alpha, 0x40);
+    assert!(!buf.pop_clip_mask());
+    assert!(buf.restore_state());
+    assert_eq!((buf.mode, buf.clip), (mode, clip));
+    assert!(!buf.restore_state());
+
+    /* Disjoint clips leave nothing to draw */
+    buf.save_state();
+    buf.set_clip(Rect::new(0, 0, 8, 8));
+    buf.intersect_clip(Rect::new(20, 20, 8, 8));
+    buf.draw_rect(Pixel::color(0xff, 0xff, 0xff), 0, 0, SIZE, SIZE);
+    buf.restore_state();
+    assert_eq!(buf, canvas());
+}
+
+#[test]
+fn save_restore_masks() {
+    let mut buf = canvas();
+    let mut mask = Buffer::new(SIZE, SIZE);
+    mask.clear(Pixel::pixel(0, 0, 0, 0));
+    mask.draw_rect(Pixel::color(0xff, 0xff, 0xff), 0, 0, 8, SIZE);
+    buf.push_clip_mask(&mask, None);
+    /* Masks pushed before a save can't be popped until it's restored */
+    buf.save_state();
+    assert!(!buf.pop_clip_mask());
+    let mut other = Buffer::new(SIZE, SIZE);
+    other.clear(Pixel::pixel(0, 0, 0, 0));
+    buf.push_clip_mask(&other, None);
+    assert!(buf.pop_clip_mask());
+    buf.push_clip_mask(&other, None);
+    assert!(buf.restore_state());
+    /* The saved mask is back on top, the one pushed after the save is gone */
+    buf.draw_rect(Pixel::color(0xff, 0, 0), 0, 0, SIZE, SIZE);
+    let mut expected = canvas();
+    expected.draw_rect(Pixel::color(0xff, 0, 0), 0, 0, 8, SIZE);
+    assert!(buf.pop_clip_mask());
+    assert!(!buf.pop_clip_mask());
+    assert_eq!(buf, expected);
+}
+
+#[test]
+fn filter() {
+    let filters = [Filter::NEAREST, Filter::BILINEAR, Filter::BICUBIC];
+    let mut buf = canvas();
+    let cat = Buffer::file("tests/cat.png").unwrap();
+    let small = Transform::new(0.0, 0.0, 0.0, 0.045, 0.045);
+    for (i, f) in filters.iter().enumerate() {
+        let x = i as i32 * 21;
+        buf.set_filter(*f);
+        buf.draw(&sprite(), x, 0, None, Some(Transform::new(0.0, 0.0, 0.0, 1.25, 1.25)));
+        buf.copy_pixels(&sprite(), x + 4, 16, Some(Rect::new(0, 2, 8, 8)), 1.5, 1.5);
+        buf.draw(&sprite(), x + 10, 36, None, Some(Transform::new(8.0, 6.0, 0.4, 0.9, 0.9)));
+        buf.draw(&cat, x, 47, None, Some(small));
+    }
+    check("filter", &buf);
+}
+
+#[test]
+fn filter_sampling() {
+    let mut src = Buffer::new(2, 1);
+    src.set_pixel(Pixel::color(0, 0, 0), 0, 0);
+    src.set_pixel(Pixel::color(0xff, 0xff, 0xff), 1, 0);
+    let mut dst = Buffer::new(8, 4);
+    dst.set_filter(Filter::BILINEAR);
+    dst.copy_pixels(&src, 0, 0, None, 4.0, 4.0);
+    /* Colors ramp between the two pixel centers and are flat past them */
+    let row: Vec<_> = (0..8).map(|x| channels(dst.get_pixel(x, 1))[0]).collect();
+    assert_eq!(&row[..2], &[0, 0]);
+    assert_eq!(&row[6..], &[0xff, 0xff]);
+    assert!(row.windows(2).all(|w| w[0] <= w[1]), "{:?}", row);
+
+    /* Transparent pixels don't bleed their color into opaque ones */
+    src.set_pixel(Pixel::pixel(0, 0, 0, 0), 0, 0);
+    dst.clear(Pixel::pixel(0, 0, 0, 0));
+    for f in &[Filter::BILINEAR, Filter::BICUBIC] {
+        dst.set_filter(*f);
+        dst.copy_pixels(&src, 0, 0, None, 4.0, 4.0);
+        for x in 0..8 {
+            let c = channels(dst.get_pixel(x, 1));
+            assert!(c[3] == 0 || c[0] == 0xff && c[1] == 0xff && c[2] == 0xff, "{:?}", c);
+        }
+    }
+}
+
+#[test]
+fn copy_pixels_sub_rect() {
+    /* The source rect is honored when scaling, including its x offset */
+    let sprite = sprite();
+    let mut a = Buffer::new(8, 8);
+    let mut b = a.clone();
+    a.copy_pixels(&sprite, 0, 0, Some(Rect::new(4, 2, 4, 4)), 2.0, 2.0);
+    b.copy_pixels(&sprite.sub_view(Rect::new(4, 2, 4, 4)).unwrap(), 0, 0, None, 2.0, 2.0);
+    assert_eq!(a, b);
+}
+
+#[test]
+fn affine() {
+    let mut buf = canvas();
+    let sprite = sprite();
+    buf.draw_affine(&sprite, None, Affine2::translate(4.0, 2.0).compose(Affine2::skew(0.6, 0.0)));
+    buf.draw_affine(
+        &sprite,
+        Some(Rect::new(0, 0, 8, 8)),
+        Affine2::new(1.5, 0.3, -0.4, 2.5, 44.0, 4.0),
+    );
+    /* A child placed relative to a rotating parent */
+    let parent = Affine2::translate(20.0, 44.0)
+        .compose(Affine2::rotate(0.5))
+        .compose(Affine2::translate(-8.0, -6.0));
+    let child = Affine2::translate(16.0, 6.0)
+        .compose(Affine2::rotate(-0.9))
+        .compose(Affine2::scale(0.75, 0.75));
+    buf.draw_affine(&sprite, None, parent);
+    buf.draw_affine(&sprite, None, parent.compose(child));
+    buf.set_filter(Filter::BILINEAR);
+    let t = Transform::new(8.0, 6.0, 0.3, 1.0, -1.25);
+    buf.draw_affine(&sprite, None, Affine2::translate(50.0, 46.0).compose(t.into()));
+    /* Singular matrices draw nothing */
+    buf.draw_affine(&sprite, None, Affine2::scale(0.0, 1.0));
+    check("affine", &buf);
+}
+
+#[test]
+fn affine_math() {
+    let close = |a: (f32, f32), b: (f32, f32)| (a.0 - b.0).abs() < 1e-4 && (a.1 - b.1).abs() < 1e-4;
+    let m = Affine2::translate(3.0, -2.0)
+        .compose(Affine2::rotate(0.7))
+        .compose(Affine2::skew(0.2, -0.1))
+        .compose(Affine2::scale(2.0, 0.5));
+    let inv = m.invert().unwrap();
+    for &p in &[(0.0, 0.0), (1.0, 5.0), (-7.5, 2.25)] {
+        assert!(close(inv.apply(m.apply(p.0, p.1).0, m.apply(p.0, p.1).1), p));
+        let composed = m.compose(Affine2::translate(1.0, 2.0)).apply(p.0, p.1);
+        assert!(close(composed, m.apply(p.0 + 1.0, p.1 + 2.0)));
+    }
+    assert!(close(Affine2::rotate(f32::consts::FRAC_PI_2).apply(1.0, 0.0), (0.0, 1.0)));
+    assert_eq!(Affine2::scale(2.0, 0.0).invert(), None);
+    let t: Affine2 = Transform::new(2.0, 1.0, 0.0, 3.0, 2.0).into();
+    assert!(close(t.apply(2.0, 1.0), (0.0, 0.0)));
+    assert!(close(t.apply(3.0, 2.0), (3.0, 2.0)));
+
+    /* Matches `draw` for placements it handles without rotating */
+    let sprite = sprite();
+    for &t in &[
+        Transform::new(0.0, 0.0, 0.0, 1.0, 1.0),
+        Transform::new(3.0, 2.0, 0.0, 1.0, 1.0),
+        Transform::new(0.0, 0.0, 0.0, 2.0, 4.0),
+    ] {
+        let mut a = canvas();
+        let mut b = canvas();
+        a.draw(&sprite, 10, 7, None, Some(t));
+        b.draw_affine(&sprite, None, Affine2::translate(10.0, 7.0).compose(t.into()));
+        assert_eq!(a, b, "{:?}", t);
+    }
+}
+
+#[test]
+fn quad() {
+    let mut buf = canvas();
+    let mut floor = Buffer::new(64, 64);
+    for y in 0..8 {
+        for x in 0..8 {
+            let c = if (x + y) % 2 == 0 { 0xe0 } else { 0x40 };
+            floor.draw_rect(Pixel::color(c, c, (x * 32) as u8), x * 8, y * 8, 8, 8);
+        }
+    }
+    let p = Point::new;
+    /* A floor receding to the horizon */
+    buf.draw_quad(&floor, None, [p(22, 34), p(42, 34), p(64, 64), p(0, 64)]);
+    /* Keystone correction and a card half way through flipping */
+    buf.set_filter(Filter::BILINEAR);
+    buf.draw_quad(&sprite(), None, [p(2, 4), p(28, 8), p(26, 22), p(4, 28)]);
+    let card = [p(36, 2), p(52, 8), p(52, 26), p(36, 32)];
+    buf.draw_quad(&sprite(), Some(Rect::new(4, 0, 12, 12)), card);
+    /* Degenerate quads draw nothing */
+    buf.draw_quad(&sprite(), None, [p(0, 0), p(10, 10), p(20, 20), p(30, 30)]);
+    check("quad", &buf);
+}
+
+#[test]
+fn quad_rect() {
+    let p = Point::new;
+    let sprite = sprite();
+    let mut a = canvas();
+    let mut b = canvas();
+    a.draw(&sprite, 5, 9, None, None);
+    b.draw_quad(&sprite, None, [p(5, 9), p(21, 9), p(21, 21), p(5, 21)]);
+    assert_eq!(a, b);
+    let t = Transform::new(0.0, 0.0, 0.0, 2.0, 2.0);
+    a.draw(&sprite, 30, 20, Some(Rect::new(2, 3, 10, 8)), Some(t));
+    let corners = [p(30, 20), p(50, 20), p(50, 36), p(30, 36)];
+    b.draw_quad(&sprite, Some(Rect::new(2, 3, 10, 8)), corners);
+    assert_eq!(a, b);
+}
+
+/* Vertices and indices of a grid of `n` by `n` cells covering `src`, with
+ * `f` placing each point */
+fn grid<F: Fn(f32, f32) -> (f32, f32)>(src: &Buffer, n: u32, f: F) -> (Vec<Vertex>, Vec<u32>) {
+    let white = Pixel::color(0xff, 0xff, 0xff);
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    for j in 0..(n + 1) {
+        for i in 0..(n + 1) {
+            let (u, v) = (src.w as f32 * i as f32 / n as f32, src.h as f32 * j as f32 / n as f32);
+            let (x, y) = f(u, v);
+            vertices.push(Vertex::new(x, y, u, v, white));
+        }
+    }
+    for j in 0..n {
+        for i in 0..n {
+            let k = j * (n + 1) + i;
+            indices.extend_from_slice(&[k, k + 1, k + n + 2, k, k + n + 2, k + n + 1]);
+        }
+    }
+    (vertices, indices)
+}
+
+#[test]
+fn triangles() {
+    let mut buf = canvas();
+    let sprite = sprite();
+    /* A mesh bent into a wave */
+    let (v, i) = grid(&sprite, 4, |u, v| {
+        (2.0 + u * 1.5 + (v * 0.5).sin() * 3.0, 2.0 + v * 1.5 + (u * 0.4).sin() * 2.0)
+    });
+    buf.draw_triangles(&sprite, &v, &i, TextureMapping::AFFINE);
+    /* Vertex colors tint a plain white source */
+    let mut white = Buffer::new(1, 1);
+    white.clear(Pixel::color(0xff, 0xff, 0xff));
+    let shaded = [
+        Vertex::new(62.0, 2.0, 0.5, 0.5, Pixel::color(0xff, 0, 0)),
+        Vertex::new(62.0, 30.0, 0.5, 0.5, Pixel::color(0, 0xff, 0)),
+        Vertex::new(36.0, 16.0, 0.5, 0.5, Pixel::pixel(0, 0, 0xff, 0x40)),
+    ];
+    buf.draw_triangles(&white, &shaded, &[0, 1, 2], TextureMapping::AFFINE);
+    /* A plane tilted away from the viewer, projected with and without
+     * perspective correction */
+    let mut checker = Buffer::new(8, 8);
+    for y in 0..8 {
+        for x in 0..8 {
+            let c = if (x + y) % 2 == 0 { 0xe0 } else { 0x30 };
+            checker.set_pixel(Pixel::color(c, c, 0x80), x, y);
+        }
+    }
+    for (k, mapping) in [TextureMapping::AFFINE, TextureMapping::PERSPECTIVE].iter().enumerate() {
+        let cx = 16.0 + k as f32 * 32.0;
+        let project = |x: f32, z: f32, u: f32, v: f32| {
+            let w = 1.0 + z;
+            let white = Pixel::color(0xff, 0xff, 0xff);
+            let mut p = Vertex::new(cx + x / w, 34.0 + 28.0 / w, u, v, white);
+            p.w = w;
+            p
+        };
+        let plane = [
+            project(-14.0, 0.0, 0.0, 8.0),
+            project(14.0, 0.0, 8.0, 8.0),
+            project(14.0, 3.0, 8.0, 0.0),
+            project(-14.0, 3.0, 0.0, 0.0),
+        ];
+        buf.draw_triangles(&checker, &plane, &[0, 1, 2, 0, 2, 3], *mapping);
+    }
+    check("triangles", &buf);
+}
+
+#[test]
+fn triangles_rect() {
+    /* Two triangles spanning a rect draw the same as `draw` */
+    let sprite = sprite();
+    let (v, i) = grid(&sprite, 1, |u, v| (u + 7.0, v + 5.0));
+    let mut a = canvas();
+    let mut b = canvas();
+    a.draw(&sprite, 7, 5, None, None);
+    b.draw_triangles(&sprite, &v, &i, TextureMapping::PERSPECTIVE);
+    assert_eq!(a, b);
+
+    /* Shared edges are drawn once, so nothing is blended twice */
+    let mut white = Buffer::new(1, 1);
+    white.clear(Pixel::color(0xff, 0xff, 0xff));
+    let (v, i) = grid(&white, 3, |u, v| (3.0 + u * 13.3, 2.0 + v * 9.7 + u * 4.1));
+    let mut c = Buffer::new(24, 24);
+    c.clear(Pixel::color(0, 0, 0));
+    c.set_alpha(0x80);
+    c.draw_triangles(&white, &v, &i, TextureMapping::AFFINE);
+    let expected = c.get_pixel(10, 10);
+    assert_ne!(expected, Pixel::color(0, 0, 0));
+    assert!(c.pixels.iter().all(|p| *p == expected || *p == Pixel::color(0, 0, 0)));
+}
+
+/* A 12x12 panel skin with 3 pixel borders, in a larger atlas at (4, 4) */
+fn skin() -> Buffer {
+    let mut atlas = Buffer::new(20, 20);
+    atlas.clear(Pixel::color(0xff, 0, 0xff));
+    atlas.draw_rect(Pixel::color(0x30, 0x50, 0x90), 4, 4, 12, 12);
+    atlas.draw_box(Pixel::color(0xf0, 0xf0, 0xf0), 4, 4, 12, 12);
+    atlas.draw_box(Pixel::color(0x80, 0xa0, 0xd0), 5, 5, 10, 10);
+    for &(x, y) in &[(5, 5), (14, 5), (5, 14), (14, 14)] {
+        atlas.draw_pixel(Pixel::color(0xff, 0xc0, 0), x, y);
+    }
+    /* Pattern in the edges and center shows the difference between tiling
+     * and stretching */
+    for i in 0..3 {
+        atlas.draw_pixel(Pixel::color(0xff, 0xff, 0), 8 + i * 2, 4);
+        atlas.draw_pixel(Pixel::color(0xff, 0xff, 0), 4, 8 + i * 2);
+        atlas.draw_pixel(Pixel::color(0x60, 0x90, 0xff), 8 + i, 8 + i);
+    }
+    atlas
+}
+
+#[test]
+fn nine_slice() {
+    let mut buf = canvas();
+    let atlas = skin();
+    let sub = Some(Rect::new(4, 4, 12, 12));
+    let mut slice = NineSlice::new(3, 3, 3, 3);
+    buf.draw_nine_slice(&atlas, sub, slice, Rect::new(2, 2, 37, 26));
+    slice.tile = true;
+    buf.draw_nine_slice(&atlas, sub, slice, Rect::new(2, 32, 37, 30));
+    /* Too small for the borders, so the corners shrink */
+    buf.draw_nine_slice(&atlas, sub, slice, Rect::new(44, 4, 4, 40));
+    buf.set_alpha(0x80);
+    buf.draw_nine_slice(&atlas, sub, NineSlice::new(2, 4, 5, 1), Rect::new(50, 20, 12, 42));
+    check("nine_slice", &buf);
+}
+
+#[test]
+fn nine_slice_corners() {
+    let atlas = skin();
+    for &tile in &[false, true] {
+        let mut slice = NineSlice::new(3, 3, 3, 3);
+        slice.tile = tile;
+        let mut buf = Buffer::new(40, 30);
+        buf.draw_nine_slice(&atlas, Some(Rect::new(4, 4, 12, 12)), slice, Rect::new(5, 3, 31, 23));
+        for y in 0..3 {
+            for x in 0..3 {
+                assert_eq!(buf.get_pixel(5 + x, 3 + y), atlas.get_pixel(4 + x, 4 + y));
+                assert_eq!(buf.get_pixel(33 + x, 23 + y), atlas.get_pixel(13 + x, 13 + y));
+            }
+        }
+        /* Nothing is drawn outside the destination */
+        assert_eq!(buf.get_pixel(4, 3), Pixel::color(0, 0, 0));
+        assert_eq!(buf.get_pixel(36, 25), Pixel::color(0, 0, 0));
+        assert_eq!(buf.get_pixel(35, 26), Pixel::color(0, 0, 0));
+        /* The center is filled */
+        if tile {
+            for y in 0..17 {
+                for x in 0..25 {
+                    let expected = atlas.get_pixel(7 + x % 6, 7 + y % 6);
+                    assert_eq!(buf.get_pixel(8 + x, 6 + y), expected, "({}, {})", x, y);
+                }
+            }
+        } else {
+            assert_eq!(buf.get_pixel(20, 13), atlas.get_pixel(9, 9));
+        }
+    }
+}
+
+#[test]
+fn triangles_behind_eye() {
+    /* Vertices at or behind the eye have no perspective projection, so their
+     * triangles are skipped instead of sampling with infinite coordinates */
+    let sprite = sprite();
+    let white = Pixel::color(0xff, 0xff, 0xff);
+    for &w in &[0.0, -1.0] {
+        let mut v = [
+            Vertex::new(4.0, 4.0, 0.0, 0.0, white),
+            Vertex::new(40.0, 8.0, 16.0, 0.0, white),
+            Vertex::new(10.0, 50.0, 0.0, 12.0, white),
+        ];
+        v[1].w = w;
+        let mut buf = canvas();
+        buf.draw_triangles(&sprite, &v, &[0, 1, 2], TextureMapping::PERSPECTIVE);
+        assert_eq!(buf, canvas());
+        /* Affine mapping ignores `w` */
+        buf.draw_triangles(&sprite, &v, &[0, 1, 2], TextureMapping::AFFINE);
+        assert_ne!(buf, canvas());
+    }
+}
+
+#[test]
+fn tiled() {
+    let mut buf = canvas();
+    let sprite = sprite();
+    /* Parallax layers scrolled by different amounts */
+    buf.draw_tiled(&sprite, None, Rect::new(0, 0, SIZE, 40), Point::new(5, 3));
+    buf.set_alpha(0xa0);
+    let atlas = skin();
+    let panel = Some(Rect::new(4, 4, 12, 12));
+    buf.draw_tiled(&atlas, panel, Rect::new(4, 20, 56, 40), Point::new(-13, 7));
+    buf.reset();
+    buf.set_clip(Rect::new(0, 44, 40, 20));
+    let dest = Rect::new(-10, 30, 100, 100);
+    buf.draw_tiled(&sprite, Some(Rect::new(8, 4, 6, 5)), dest, Point::new(0, 0));
+    check("tiled", &buf);
+}
+
+#[test]
+fn tiled_offsets() {
+    /* Matches drawing every tile by hand */
+    let sprite = sprite();
+    let sub = Rect::new(3, 2, 7, 5);
+    let dest = Rect::new(6, 4, 41, 33);
+    for &(ox, oy) in &[(0, 0), (3, 1), (-4, -9), (700, 52), (7, 5)] {
+        let mut a = canvas();
+        let mut b = canvas();
+        a.set_clip(Rect::new(0, 10, 40, 54));
+        b.set_clip(Rect::new(0, 10, 40, 54));
+        a.draw_tiled(&sprite, Some(sub), dest, Point::new(ox, oy));
+        b.save_state();
+        b.intersect_clip(dest);
+        let (sx, sy) = (ox.rem_euclid(sub.w), oy.rem_euclid(sub.h));
+        for y in (0..dest.h + sub.h).step_by(sub.h as usize) {
+            for x in (0..dest.w + sub.w).step_by(sub.w as usize) {
+                b.draw(&sprite, dest.x + x - sx, dest.y + y - sy, Some(sub), None);
+            }
+        }
+        b.restore_state();
+        assert_eq!(a, b, "offset ({}, {})", ox, oy);
+    }
+}
+
+#[test]
+fn clip() {
+    let mut buf = canvas();
+    buf.set_clip(Rect::new(8, 12, 40, 30));
+    buf.draw_rect(Pixel::color(0x80, 0x80, 0x80), 0, 0, SIZE, SIZE);
+    buf.draw_line(Pixel::color(0xff, 0, 0), 0, 0, SIZE, SIZE);
+    buf.draw_circle(Pixel::color(0, 0xff, 0), 44, 40, 10);
+    buf.draw(&sprite(), 0, 4, None, None);
+    check("clip", &buf);
+}
+
+fn blend_scene(buf: &mut Buffer, src: &Buffer, mode: BlendMode) {
+    buf.set_blend(mode);
+    buf.set_color(Pixel::color(0xc0, 0x60, 0xff));
+    buf.draw_rect(Pixel::color(0x90, 0x40, 0xe0), 0, 4, SIZE, 16);
+    buf.set_alpha(0x80);
+    buf.draw_rect(Pixel::color(0x90, 0x40, 0xe0), 0, 24, SIZE, 16);
+    buf.set_alpha(0xff);
+    buf.draw(src, 4, 44, None, None);
+    buf.draw(src, 40, 44, None, Some(Transform::new(0.0, 0.0, 0.0, -1.0, 1.0)));
+}
+
+#[test]
+fn blend() {
+    let src = sprite();
+    for &(mode, name) in &BLEND_MODES {
+        let mut buf = bands();
+        blend_scene(&mut buf, &src, mode);
+        check(&format!("blend_{}", name), &buf);
+    }
+}
+
+#[test]
+fn pixel_formats() {
+    /* Every storage format must render exactly like the native one, whatever
+     * the format of the source buffer */
+    for (i, &fmt) in PIXEL_FORMATS.iter().enumerate() {
+        let mut src = sprite();
+        src.set_format(PIXEL_FORMATS[(i + 1) % PIXEL_FORMATS.len()]);
+        let mut buf = bands();
+        buf.set_format(fmt);
+        blend_scene(&mut buf, &src, BlendMode::MULTIPLY);
+        buf.set_format(PixelFormat::NATIVE);
+        check("blend_multiply", &buf);
+
+        let mut buf = canvas();
+        buf.set_format(fmt);
+        buf.copy_pixels(&src, 2, 2, None, 1.0, 1.0);
+        buf.copy_pixels(&src, 30, 2, Some(Rect::new(4, 2, 8, 8)), 1.0, 1.0);
+        buf.copy_pixels(&src, 4, 24, None, 2.0, 2.0);
+        buf.copy_pixels(&src, 50, 50, None, 1.0, 1.0);
+        buf.set_format(PixelFormat::NATIVE);
+        check("copy_pixels", &buf);
+    }
+}
+
+#[test]
+fn blend_transparent() {
+    let mut buf = Buffer::new(SIZE, SIZE);
+    buf.clear(Pixel::pixel(0x40, 0x80, 0xc0, 0x60));
+    buf.set_alpha(0xa0);
+    buf.draw_rect(Pixel::pixel(0xff, 0x20, 0x20, 0xc0), 8, 8, 40, 40);
+    buf.draw(&sprite(), 30, 30, None, None);
+    check("blend_transparent", &buf);
+}
+
+#[test]
+fn draw_basic() {
+    let src = sprite();
+    let mut buf = canvas();
+    buf.draw(&src, 2, 2, None, None);
+    buf.draw(&src, 30, 4, Some(Rect::new(4, 2, 8, 8)), None);
+    buf.draw(&src, -6, 40, None, None);
+    buf.draw(&src, 56, 58, None, None);
+    buf.draw(&src, 32, 32, None, Some(Transform::new(8.0, 6.0, 0.0, 1.0, 1.0)));
+    check("draw_basic", &buf);
+}
+
+#[test]
+fn draw_scaled() {
+    let src = sprite();
+    let mut buf = canvas();
+    buf.draw(&src, 2, 2, None, Some(Transform::new(0.0, 0.0, 0.0, 2.0, 2.0)));
+    buf.draw(&src, 40, 2, None, Some(Transform::new(0.0, 0.0, 0.0, 0.5, 1.5)));
+    buf.draw(&src, 30, 40, None, Some(Transform::new(8.0, 6.0, 0.0, -1.5, 1.5)));
+    buf.draw(&src, 56, 40, None, Some(Transform::new(0.0, 0.0, 0.0, 1.0, -2.0)));
+    buf.draw(&src, -10, 50, None, Some(Transform::new(0.0, 0.0, 0.0, 2.0, 2.0)));
+    check("draw_scaled", &buf);
+}
+
+#[test]
+fn draw_rotated() {
+    let src = sprite();
+    for &deg in &[30, 90, 135, 180, 250, 315] {
+        let mut buf = canvas();
+        let r = (deg as f32).to_radians();
+        buf.draw(&src, 20, 20, None, Some(Transform::new(8.0, 6.0, r, 1.0, 1.0)));
+        buf.draw(&src, 44, 20, None, Some(Transform::new(8.0, 6.0, r, 1.5, 0.75)));
+        buf.draw(&src, 20, 44, None, Some(Transform::new(8.0, 6.0, r, -1.0, 1.0)));
+        buf.draw(&src, 60, 56, None, Some(Transform::new(0.0, 0.0, r, 1.5, 1.5)));
+        check(&format!("draw_rotated_{}", deg), &buf);
+    }
+}
+
+#[test]
+fn copy_pixels() {
+    let src = sprite();
+    let mut buf = canvas();
+    buf.copy_pixels(&src, 2, 2, None, 1.0, 1.0);
+    buf.copy_pixels(&src, 30, 2, Some(Rect::new(4, 2, 8, 8)), 1.0, 1.0);
+    buf.copy_pixels(&src, 4, 24, None, 2.0, 2.0);
+    buf.copy_pixels(&src, 50, 50, None, 1.0, 1.0);
+    check("copy_pixels", &buf);
+}
+
+#[test]
+fn flood_fill() {
+    let mut buf = canvas();
+    buf.draw_box(Pixel::color(0xff, 0xff, 0xff), 8, 8, 40, 30);
+    buf.draw_line(Pixel::color(0xff, 0xff, 0xff), 8, 8, 47, 37);
+    buf.flood_fill(Pixel::color(0xff, 0, 0), 30, 12);
+    buf.flood_fill(Pixel::color(0, 0, 0xff), 60, 60);
+    check("flood_fill", &buf);
+}
+
+#[test]
+fn flood_fill_with() {
+    let mut buf = canvas();
+    let white = Pixel::color(0xff, 0xff, 0xff);
+    /* Diagonal steps in the line only stop 4-connected fills */
+    buf.draw_box(white, 2, 2, 28, 28);
+    buf.draw_line(white, 2, 2, 29, 29);
+    buf.flood_fill_with(Pixel::color(0xff, 0, 0), 20, 8, &FloodFill::new(0, Connectivity::EIGHT));
+    /* Tolerance spreads across the nearby shades of the gradient */
+    let mut g = Gradient::linear(34.0, 0.0, 61.0, 0.0);
+    g.add_stop(0.0, Pixel::color(0, 0x40, 0)).add_stop(1.0, Pixel::color(0, 0xff, 0));
+    buf.draw_rect(&g, 34, 2, 28, 28);
+    let opts = FloodFill::new(0x30, Connectivity::FOUR);
+    buf.flood_fill_with(Pixel::color(0xff, 0xff, 0), 48, 16, &opts);
+    /* Stops at the clip rect and blends through the draw mode */
+    buf.draw_box(white, 2, 34, 60, 28);
+    buf.set_clip(Rect::new(0, 0, 40, SIZE));
+    buf.set_alpha(0x80);
+    let mut opts = FloodFill::new(0, Connectivity::FOUR);
+    opts.blend = true;
+    buf.flood_fill_with(Pixel::color(0, 0x80, 0xff), 20, 48, &opts);
+    check("flood_fill_with", &buf);
+}
+
+#[test]
+fn flood_fill_large() {
+    /* A recursive fill would run out of stack long before covering this */
+    let mut buf = Buffer::new(2048, 2048);
+    buf.clear(Pixel::color(0, 0, 0));
+    for x in (8..2040).step_by(16) {
+        buf.draw_rect(Pixel::color(0xff, 0xff, 0xff), x, if x % 32 == 8 { 0 } else { 8 }, 1, 2040);
+    }
+    buf.flood_fill(Pixel::color(0xff, 0, 0), 0, 0);
+    assert!(buf.pixels.iter().all(|p| *p != Pixel::color(0, 0, 0)));
+    assert_eq!(buf.get_pixel(8, 2047), Pixel::color(0xff, 0, 0));
+}
+
+#[test]
+fn select_region() {
+    let mut buf = canvas();
+    let white = Pixel::color(0xff, 0xff, 0xff);
+    buf.draw_box(white, 8, 8, 40, 30);
+    buf.draw_line(white, 8, 8, 47, 37);
+    let mut g = Gradient::linear(0.0, 44.0, 0.0, 60.0);
+    g.add_stop(0.0, Pixel::color(0x20, 0x30, 0x40)).add_stop(1.0, Pixel::color(0x80, 0x30, 0x40));
+    buf.draw_rect(&g, 4, 44, 56, 16);
+    buf.set_clip(Rect::new(0, 0, 56, SIZE));
+    let cases = [
+        (30, 12, FloodFill::new(0, Connectivity::FOUR)),
+        (30, 12, FloodFill::new(0, Connectivity::EIGHT)),
+        (60, 60, FloodFill::new(0, Connectivity::FOUR)),
+        (60, 60, FloodFill::new(0x20, Connectivity::FOUR)),
+        (60, 60, FloodFill::new(0xff, Connectivity::FOUR)),
+    ];
+    /* The mask covers exactly the pixels a fill from the same point changes */
+    let marker = Pixel::pixel(1, 2, 3, 4);
+    for &(x, y, ref opts) in &cases {
+        let mask = buf.select_region_with(x, y, opts);
+        let mut filled = buf.clone();
+        filled.flood_fill_with(marker, x, y, opts);
+        for py in 0..SIZE {
+            for px in 0..SIZE {
+                let selected = filled.get_pixel(px, py) == marker;
+                let expected = if selected {
+                    Pixel::color(0xff, 0xff, 0xff)
+                } else {
+                    Pixel::pixel(0, 0, 0, 0)
+                };
+                assert_eq!(mask.get_pixel(px, py), expected, "{:?} ({}, {})", opts, px, py);
+            }
+        }
+    }
+    assert_eq!(buf.select_region(30, 12, 0), buf.select_region_with(30, 12, &cases[1].2));
+    /* Nothing is selected from outside the clip rect */
+    let mask = buf.select_region(60, 4, 0xff);
+    assert!(mask.pixels.iter().all(|p| *p == Pixel::pixel(0, 0, 0, 0)));
+}
+
+#[test]
+fn select_region_matches_flood_fill() {
+    /* Black squares touching only at their corners, across a white diagonal */
+    let mut buf = Buffer::new(4, 4);
+    buf.clear(Pixel::color(0xff, 0xff, 0xff));
+    buf.draw_rect(Pixel::color(0, 0, 0), 0, 0, 2, 2);
+    buf.draw_rect(Pixel::color(0, 0, 0), 2, 2, 2, 2);
+    let marker = Pixel::color(0xff, 0, 0);
+    let mask = buf.select_region(0, 0, 0);
+    buf.flood_fill(marker, 0, 0);
+    for y in 0..4 {
+        for x in 0..4 {
+            let selected = mask.get_pixel(x, y) == Pixel::color(0xff, 0xff, 0xff);
+            assert_eq!(selected, buf.get_pixel(x, y) == marker, "({}, {})", x, y);
+        }
+    }
+    assert_eq!(buf.get_pixel(3, 3), marker);
+}
@@ -0,0 +1,143 @@
+extern crate sera;
+
+use sera::*;
+
+const PAD: Pixel = Pixel { word: 0xdead_beef };
+
+fn scene<S: PixelsMut>(b: &mut Buffer<S>, src: &Buffer) {
+    b.clear(Pixel::color(0x20, 0x30, 0x40));
+    b.draw_rect(Pixel::pixel(0xff, 0x80, 0x00, 0xc0), 2, 3, 9, 7);
+    b.draw_line(Pixel::color(0x00, 0xff, 0x00), 0, 15, 15, 0);
+    b.draw_circle(Pixel::color(0x00, 0x00, 0xff), 10, 10, 4);
+    b.draw_pixel(Pixel::color(0xff, 0xff, 0xff), 15, 15);
+    b.draw(src, 4, 1, None, None);
+    b.draw(src, 8, 8, None, Some(Transform::new(1.0, 1.0, 0.5, 1.5, 1.5)));
+    b.copy_pixels(src, -2, 12, None, 1.0, 1.0);
+}
+
+fn sprite() -> Buffer {
+    let mut src = Buffer::new(5, 4);
+    for y in 0..4 {
+        for x in 0..5 {
+            src.set_pixel(Pixel::pixel(x as u8 * 50, y as u8 * 80, 0x90, 0xa0), x, y);
+        }
+    }
+    src
+}
+
+fn assert_same<S: Pixels, T: Pixels>(a: &Buffer<S>, b: &Buffer<T>) {
+    assert_eq!(a.get_size(), b.get_size());
+    let (w, h) = a.get_size();
+    for y in 0..h {
+        for x in 0..w {
+            assert_eq!(a.get_pixel(x, y), b.get_pixel(x, y), "({}, {})", x, y);
+        }
+    }
+}
+
+#[test]
+fn padded_view() {
+    let src = sprite();
+    let mut expected = Buffer::new(16, 16);
+    scene(&mut expected, &src);
+
+    let pitch = 20;
+    let mut mem = vec![PAD; (pitch * 16) as usize];
+    {
+        let mut view =
+            BufferViewMut::from_slice_mut(&mut mem, 16, 16, pitch, PixelFormat::NATIVE).unwrap();
+        scene(&mut view, &src);
+        assert_same(&view, &expected);
+    }
+    /* Padding past the end of each row is left untouched */
+    for row in mem.chunks(pitch as usize) {
+        assert!(row[16..].iter().all(|p| *p == PAD));
+    }
+}
+
+#[test]
+fn bytes_view() {
+    let src = sprite();
+    let mut expected = Buffer::new(16, 16);
+    expected.set_format(PixelFormat::RGBA);
+    scene(&mut expected, &src);
+
+    let pitch = 16 * 4 + 8;
+    let mut words = vec![0u32; pitch * 16 / 4];
+    let bytes =
+        unsafe { std::slice::from_raw_parts_mut(words.as_mut_ptr() as *mut u8, pitch * 16) };
+    let mut view =
+        BufferViewMut::from_bytes_mut(bytes, 16, 16, pitch, PixelFormat::RGBA).unwrap();
+    scene(&mut view, &src);
+    assert_same(&view, &expected);
+    assert_eq!(view.pitch, 18);
+}
+
+#[test]
+fn sub_view() {
+    let src = sprite();
+    let mut expected = Buffer::new(32, 32);
+    expected.clear(Pixel::color(0, 0, 0));
+    let mut actual = expected.clone();
+
+    /* Drawing into a sub view matches an offset draw clipped to the rect */
+    expected.set_clip(Rect::new(6, 9, 16, 16));
+    expected.draw(&src, 6 + 4, 9 + 1, None, None);
+    expected.draw_rect(Pixel::color(0xff, 0, 0), 6 - 3, 9 + 10, 40, 3);
+    {
+        let mut view = actual.sub_view_mut(Rect::new(6, 9, 16, 16)).unwrap();
+        assert_eq!(view.clip, Rect::new(0, 0, 16, 16));
+        view.draw(&src, 4, 1, None, None);
+        view.draw_rect(Pixel::color(0xff, 0, 0), -3, 10, 40, 3);
+    }
+    assert_same(&actual, &expected);
+
+    /* Read-only views work as drawing sources */
+    let mut from_view = Buffer::new(32, 32);
+    let mut from_sub = Buffer::new(32, 32);
+    from_view.draw(&actual.view(), 0, 0, Some(Rect::new(6, 9, 16, 16)), None);
+    from_sub.draw(&actual.sub_view(Rect::new(6, 9, 16, 16)).unwrap(), 0, 0, None, None);
+    assert_same(&from_view, &from_sub);
+}
+
+#[test]
+fn view_errors() {
+    let mut mem = vec![PAD; 40];
+    match BufferView::from_slice(&mem, 8, 4, 6, PixelFormat::NATIVE) {
+        Err(Error::InvalidPitch { pitch: 6, w: 8 }) => (),
+        r => panic!("unexpected {:?}", r.map(|_| ())),
+    }
+    match BufferView::from_slice(&mem, 8, 5, 10, PixelFormat::NATIVE) {
+        Err(Error::SliceTooSmall {
+            expected: 48,
+            found: 40,
+        }) => (),
+        r => panic!("unexpected {:?}", r.map(|_| ())),
+    }
+    match BufferView::from_slice(&mem, 0, 5, 10, PixelFormat::NATIVE) {
+        Err(Error::InvalidSize { w: 0, h: 5 }) => (),
+        r => panic!("unexpected {:?}", r.map(|_| ())),
+    }
+    /* The last row doesn't need the padding */
+    assert!(BufferView::from_slice(&mem, 8, 4, 10, PixelFormat::NATIVE).is_ok());
+
+    let mut buf = Buffer::new(8, 8);
+    match buf.sub_view_mut(Rect::new(4, 4, 5, 2)) {
+        Err(Error::SubRectOutOfBounds { .. }) => (),
+        r => panic!("unexpected {:?}", r.map(|_| ())),
+    }
+
+    let mut bytes = [0u8; 4 * 40 + 1];
+    let aligned = (bytes.as_ptr() as usize).is_multiple_of(4);
+    let (start, end) = if aligned { (1, 161) } else { (0, 160) };
+    match BufferViewMut::from_bytes_mut(&mut bytes[start..end], 8, 4, 40, PixelFormat::NATIVE) {
+        Err(Error::UnalignedSlice) => (),
+        r => panic!("unexpected {:?}", r.map(|_| ())),
+    }
+    let words = &mut mem[..];
+    let bytes = unsafe { std::slice::from_raw_parts_mut(words.as_mut_ptr() as *mut u8, 160) };
+    match BufferViewMut::from_bytes_mut(bytes, 8, 4, 34, PixelFormat::NATIVE) {
+        Err(Error::UnalignedSlice) => (),
+        r => panic!("unexpected {:?}", r.map(|_| ())),
+    }
+}